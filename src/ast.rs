@@ -1,7 +1,7 @@
 use std::fmt::{self, Display};
 use std::ops::Range;
 
-use crate::value::ValueType;
+use crate::{dice::Dice, format::FormatSpec, value::ValueType};
 
 pub type Span = Range<usize>;
 
@@ -26,6 +26,12 @@ impl PartialEq<Expr> for Spanned {
     }
 }
 
+impl From<i64> for Spanned {
+    fn from(f: i64) -> Self {
+        Self(Expr::Literal(Literal::Int(f)), 0..1)
+    }
+}
+
 impl From<f64> for Spanned {
     fn from(f: f64) -> Self {
         Self(Expr::Literal(Literal::Num(f)), 0..1)
@@ -78,12 +84,27 @@ impl AsRef<Spanned> for Spanned {
     }
 }
 
+impl Spanned {
+    // the dotted name this refers to, if it's a plain identifier or a chain of accesses
+    pub fn path(&self) -> Option<String> {
+        match &self.0 {
+            Expr::Ident(name) => Some(name.clone()),
+            Expr::Access(lhs, rhs) => Some(lhs.path()? + "." + &rhs.path()?),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Literal {
+    Int(i64),
     Num(f64),
     String(String),
     Bool(bool),
     Array(Vec<Spanned>),
+    // fields in the order they were written
+    Record(Vec<(String, Spanned)>),
+    Dice(Dice),
     Null,
 }
 
@@ -100,12 +121,17 @@ pub enum InfixOp {
     Pow,
     Mul,
     Div,
+    // divide, then round to a whole number
+    FloorDiv, // `div`, rounding down
+    RoundDiv, // `rdiv`, rounding halves up
+    EvenDiv,  // `ediv`, rounding halves to the nearest even number
     Mod,
     Add,
     Sub,
     In,
     Range,
     IRange,
+    By, // `range by step`
 }
 
 impl Display for InfixOp {
@@ -122,12 +148,16 @@ impl Display for InfixOp {
             Self::Pow => "Pow",
             Self::Mul => "Mul",
             Self::Div => "Div",
+            Self::FloorDiv => "FloorDiv",
+            Self::RoundDiv => "RoundDiv",
+            Self::EvenDiv => "EvenDiv",
             Self::Mod => "Mod",
             Self::Add => "Add",
             Self::Sub => "Sub",
             Self::In => "In",
             Self::Range => "Range",
             Self::IRange => "IRange",
+            Self::By => "By",
         };
 
         write!(f, "{}", out)
@@ -139,7 +169,13 @@ pub enum Expr {
     Literal(Literal),
     Ident(String),
     Not(Box<Spanned>),
+    Neg(Box<Spanned>),
     InfixOp(Box<Spanned>, InfixOp, Box<Spanned>),
+    // `1 <= x <= 20`, meaning `1 <= x && x <= 20` with `x` only evaluated once
+    Compare {
+        first: Box<Spanned>,
+        rest: Vec<(InfixOp, Spanned)>,
+    },
     Index(Box<Spanned>, Box<Spanned>),
     Conditional {
         condition: Box<Spanned>,
@@ -148,12 +184,73 @@ pub enum Expr {
     },
     Access(Box<Spanned>, Box<Spanned>),
     Call(Box<Spanned>, Vec<Spanned>),
+    Macro(String, Vec<Spanned>),
+    // `use "rules/core.plm" as core;`, replaced by a block of everything in that file once it's loaded
+    Use {
+        path: String,
+        alias: String,
+    },
+    // `{ let name = value; body }`, where `name` is only visible in `body`
+    Let {
+        name: String,
+        value: Box<Spanned>,
+        body: Box<Spanned>,
+    },
+    // `match level { 1..=4 => 2, 5 | 6 => 3, _ => 4 }`, where each arm has one or more patterns
+    Match {
+        value: Box<Spanned>,
+        arms: Vec<(Vec<Spanned>, Spanned)>,
+    },
+    // `"{name} ({modifier:+})"`
+    Format(Vec<FormatPart>),
+    // `[item for var in source if condition]`
+    Comprehension {
+        item: Box<Spanned>,
+        var: String,
+        source: Box<Spanned>,
+        condition: Option<Box<Spanned>>,
+    },
+    // `|x, y| body`, or a function declared with `fn name(x, y) = body;`
+    Lambda {
+        name: Option<String>,
+        params: Vec<String>,
+        body: Box<Spanned>,
+    },
     Assign {
         names: Vec<String>,
         value: Box<Spanned>,
+        constraint: Option<Box<Spanned>>,
+        modifiers: Modifiers,
+        annotations: Annotations,
     },
     Error,
-    Input(String, ValueType),
+    Input {
+        name: String,
+        kind: ValueType,
+        default: Option<Box<Spanned>>,
+        constraint: Option<Box<Spanned>>,
+        modifiers: Modifiers,
+        annotations: Annotations,
+    },
+    Block {
+        name: String,
+        items: Vec<Spanned>,
+        annotations: Annotations,
+    },
+    // `common! { .. }`, declarations repeated for every member of the block it's in
+    Template(Vec<Spanned>),
+    // a name declared without a value, which gets one from a template
+    Member {
+        name: String,
+        modifiers: Modifiers,
+        annotations: Annotations,
+    },
+}
+
+impl From<i64> for Expr {
+    fn from(f: i64) -> Self {
+        Self::Literal(Literal::Int(f))
+    }
 }
 
 impl From<f64> for Expr {
@@ -192,6 +289,35 @@ impl From<Vec<Expr>> for Expr {
     }
 }
 
+// metadata for the host like `#[label("Strength"), order(1)]`, in the order it was written
+pub type Annotations = Vec<(String, Literal)>;
+
+// keywords in front of a declaration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub public: bool, // `pub`, visible to the host
+    pub output: bool, // `out`, one of the results of the sheet
+}
+
+// a piece of an interpolated string
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatPart {
+    Text(String),
+    Value(Spanned, FormatSpec),
+}
+
+// a piece of a string as it's lexed, where interpolations are still source code
+// `offset` is where the source starts, relative to the start of the string
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StringPart {
+    Text(String),
+    Interpolation {
+        source: String,
+        spec: Option<String>,
+        offset: usize,
+    },
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Token {
     Ident(String),
@@ -200,10 +326,23 @@ pub enum Token {
     If,
     Else,
     String(String),
+    Format(Vec<StringPart>),
     Num(String),
+    Dice(String),
     Bool(bool),
+    Null,
     Input,
+    Where,
+    Pub,
+    Out,
+    Fn,
+    For,
+    Match,
+    Let,
+    Use,
+    As,
     Type(String),
+    Macro(String),
 }
 
 impl Display for Token {
@@ -215,9 +354,22 @@ impl Display for Token {
             Token::If => write!(f, "If"),
             Token::Else => write!(f, "Else"),
             Token::String(e) => write!(f, "{}", e),
+            Token::Format(_) => write!(f, "FormatString"),
             Token::Num(e) => write!(f, "{}", e),
+            Token::Dice(e) => write!(f, "{}", e),
             Token::Bool(e) => write!(f, "{}", e),
+            Token::Null => write!(f, "null"),
             Token::Input => write!(f, "Input"),
+            Token::Where => write!(f, "Where"),
+            Token::Pub => write!(f, "Pub"),
+            Token::Out => write!(f, "Out"),
+            Token::Fn => write!(f, "Fn"),
+            Token::For => write!(f, "For"),
+            Token::Match => write!(f, "Match"),
+            Token::Let => write!(f, "Let"),
+            Token::Use => write!(f, "Use"),
+            Token::As => write!(f, "As"),
+            Token::Macro(e) => write!(f, "{}!", e),
             Token::Type(_) => write!(f, "TypeName"),
         }
     }
@@ -1,7 +1,10 @@
 use std::env;
 
-use chumsky::{Stream, Parser};
-use plum::{lexer, error::{Error, ChumskyAriadne}, parser, Spanned};
+use chumsky::{Parser, Stream};
+use plum::{
+    error::{ChumskyAriadne, Error},
+    lexer, parser, Spanned,
+};
 
 fn main() {
     let path = &env::args().collect::<Vec<String>>()[1];
@@ -41,4 +44,4 @@ fn read(input: &str) -> Result<Vec<Spanned>, Vec<Error>> {
     }
 
     Ok(parsed.unwrap())
-}
\ No newline at end of file
+}
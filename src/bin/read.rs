@@ -1,19 +1,17 @@
 use std::env;
 
-use plum::{error::ChumskyAriadne, interpreter::interpret};
+use plum::{error::ChumskyAriadne, interpreter::interpret_file, sources::SourceMap};
 
 fn main() {
     let path = &env::args().collect::<Vec<String>>()[1];
-    let file = std::fs::read(path).unwrap();
-    let source = String::from_utf8(file).unwrap();
-    let source = source.as_ref();
+    let mut sources = SourceMap::default();
 
-    let evaluated = interpret(source);
+    let evaluated = interpret_file(path, &mut sources);
 
     match evaluated {
         Err(errs) => {
             for err in errs {
-                err.display(path, source, 0);
+                err.display_in(&sources, 0);
             }
         }
         Ok(out) => println!("{:#?}", out),
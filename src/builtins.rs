@@ -0,0 +1,494 @@
+use std::cmp::Ordering;
+
+use crate::{
+    ast::Span,
+    error::{Error, TypeErrorCtx},
+    eval::{call, is_pending},
+    function::Function,
+    value::{SpannedValue, Value, ValueType, MAX_ITEMS},
+};
+
+// takes the evaluated arguments and the span of the whole call
+pub type Builtin = fn(Vec<SpannedValue>, Span) -> Result<Value, Error>;
+
+// the standard library, looked up by name when a call is evaluated
+pub fn get(name: &str) -> Option<Builtin> {
+    let builtin: Builtin = match name {
+        "sum" => sum,
+        "min" => min,
+        "max" => max,
+        "len" => len,
+        "floor" => floor,
+        "ceil" => ceil,
+        "round" => round,
+        "abs" => abs,
+        "clamp" => clamp,
+        "repeat" => repeat,
+        "array" => to_array,
+        "map" => map,
+        "filter" => filter,
+        "fold" => fold,
+        "reduce" => reduce,
+        "any" => any,
+        "all" => all,
+        "count" => count,
+        "sort" => sort,
+        _ => return None,
+    };
+
+    Some(builtin)
+}
+
+fn arity(name: &str, args: &[SpannedValue], expected: usize, span: &Span) -> Result<(), Error> {
+    if args.len() == expected {
+        Ok(())
+    } else {
+        Err(Error::ArityError {
+            name: name.to_owned(),
+            expected,
+            got: args.len(),
+            span: span.clone(),
+        })
+    }
+}
+
+// for functions with an optional last argument
+fn arity_between(
+    name: &str,
+    args: &[SpannedValue],
+    min: usize,
+    max: usize,
+    span: &Span,
+) -> Result<(), Error> {
+    if args.len() < min {
+        arity(name, args, min, span)
+    } else if args.len() > max {
+        arity(name, args, max, span)
+    } else {
+        Ok(())
+    }
+}
+
+// rounded numbers are ints, unless they're too big for one
+fn whole(e: f64) -> Value {
+    if e.is_finite() && e.abs() < i64::MAX as f64 {
+        Value::Int(e as i64)
+    } else {
+        Value::Num(e)
+    }
+}
+
+fn num(arg: &SpannedValue, index: usize) -> Result<f64, Error> {
+    match arg.0.as_num() {
+        Some(e) => Ok(e),
+        None => Err(Error::TypeError {
+            expected: ValueType::Num.into(),
+            got: arg.clone(),
+            context: TypeErrorCtx::Argument { index },
+        }),
+    }
+}
+
+// ranges can be used anywhere an array can
+fn array(arg: &SpannedValue, index: usize) -> Result<Vec<SpannedValue>, Error> {
+    match arg.0.items(&arg.1) {
+        Some(e) => e,
+        None => Err(Error::TypeError {
+            expected: vec![ValueType::Array, ValueType::Range],
+            got: arg.clone(),
+            context: TypeErrorCtx::Argument { index },
+        }),
+    }
+}
+
+// functions over a collection take either a single array or any amount of separate arguments
+fn items(name: &str, args: Vec<SpannedValue>, span: &Span) -> Result<Vec<SpannedValue>, Error> {
+    match args.as_slice() {
+        [] => Err(Error::ArityError {
+            name: name.to_owned(),
+            expected: 1,
+            got: 0,
+            span: span.clone(),
+        }),
+        [only] => array(only, 0),
+        _ => Ok(args),
+    }
+}
+
+fn sum(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    let mut items = items("sum", args, &span)?.into_iter();
+
+    let first = match items.next() {
+        Some(e) => e,
+        None => return Ok(Value::Int(0)),
+    };
+
+    items
+        .try_fold(first, |acc, item| {
+            let span = acc.1.start.min(item.1.start)..acc.1.end.max(item.1.end);
+
+            Ok(SpannedValue(acc.add(item)?, span))
+        })
+        .map(|total| total.0)
+}
+
+fn extreme(name: &str, args: Vec<SpannedValue>, span: Span, max: bool) -> Result<Value, Error> {
+    let separate = args.len() > 1;
+    let array_span = args.first().map_or(span.clone(), |arg| arg.1.clone());
+    let items = items(name, args, &span)?;
+    let mut best: Option<(f64, Value)> = None;
+
+    for (idx, item) in items.into_iter().enumerate() {
+        let value = num(&item, if separate { idx } else { 0 })?;
+
+        let better = match best {
+            Some((prev, _)) if max => value > prev,
+            Some((prev, _)) => value < prev,
+            None => true,
+        };
+
+        if better {
+            best = Some((value, item.0));
+        }
+    }
+
+    // there's always an argument by now, so it's an empty array
+    match best {
+        Some((_, value)) => Ok(value),
+        None => Err(Error::EmptyError {
+            name: name.to_owned(),
+            span: array_span,
+        }),
+    }
+}
+
+fn min(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    extreme("min", args, span, false)
+}
+
+fn max(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    extreme("max", args, span, true)
+}
+
+fn len(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("len", &args, 1, &span)?;
+
+    match &args[0].0 {
+        Value::Array(e) => Ok(Value::Int(e.len() as i64)),
+        Value::String(e) => Ok(Value::Int(e.chars().count() as i64)),
+        Value::Record(e) => Ok(Value::Int(e.len() as i64)),
+        Value::Range(e) => match i64::try_from(e.len()) {
+            Ok(len) => Ok(Value::Int(len)),
+            Err(_) => Err(Error::OverflowError { span }),
+        },
+        _ => Err(Error::TypeError {
+            expected: vec![
+                ValueType::Array,
+                ValueType::String,
+                ValueType::Record,
+                ValueType::Range,
+            ],
+            got: args[0].clone(),
+            context: TypeErrorCtx::Argument { index: 0 },
+        }),
+    }
+}
+
+fn floor(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("floor", &args, 1, &span)?;
+
+    Ok(whole(num(&args[0], 0)?.floor()))
+}
+
+fn ceil(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("ceil", &args, 1, &span)?;
+
+    Ok(whole(num(&args[0], 0)?.ceil()))
+}
+
+fn round(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("round", &args, 1, &span)?;
+
+    Ok(whole(num(&args[0], 0)?.round()))
+}
+
+fn abs(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("abs", &args, 1, &span)?;
+
+    match args[0].0 {
+        Value::Int(e) => e
+            .checked_abs()
+            .map(Value::Int)
+            .ok_or(Error::OverflowError { span }),
+        _ => Ok(Value::Num(num(&args[0], 0)?.abs())),
+    }
+}
+
+fn clamp(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("clamp", &args, 3, &span)?;
+
+    let value = num(&args[0], 0)?;
+    let low = num(&args[1], 1)?;
+    let high = num(&args[2], 2)?;
+
+    match (&args[0].0, &args[1].0, &args[2].0) {
+        (Value::Int(value), Value::Int(low), Value::Int(high)) => {
+            Ok(Value::Int(*value.max(low).min(high)))
+        }
+        _ => Ok(Value::Num(value.max(low).min(high))),
+    }
+}
+
+fn repeat(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("repeat", &args, 2, &span)?;
+
+    let times = match args[1].0 {
+        Value::Int(e) if e >= 0 => e as u128,
+        _ => {
+            return Err(Error::TypeError {
+                expected: ValueType::Int.into(),
+                got: args[1].clone(),
+                context: TypeErrorCtx::Argument { index: 1 },
+            })
+        }
+    };
+
+    if times > MAX_ITEMS {
+        return Err(Error::SizeError { len: times, span });
+    }
+
+    Ok(Value::Array(vec![args[0].clone(); times as usize]))
+}
+
+fn to_array(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("array", &args, 1, &span)?;
+
+    Ok(Value::Array(array(&args[0], 0)?))
+}
+
+fn function(arg: &SpannedValue, index: usize) -> Result<Function, Error> {
+    match &arg.0 {
+        Value::Function(e) => Ok(e.clone()),
+        _ => Err(Error::TypeError {
+            expected: ValueType::Function.into(),
+            got: arg.clone(),
+            context: TypeErrorCtx::Argument { index },
+        }),
+    }
+}
+
+// calls `function` for a single element, so errors point at the element that caused them
+// returns `None` when the result depends on an input that hasn't been set
+fn apply(
+    function: &Function,
+    args: Vec<SpannedValue>,
+    element: &Span,
+) -> Result<Option<Value>, Error> {
+    let result = call(function, args, element)?;
+
+    if is_pending(&result) {
+        Ok(None)
+    } else {
+        Ok(Some(result))
+    }
+}
+
+fn test(function: Option<&Function>, item: &SpannedValue) -> Result<Option<bool>, Error> {
+    let result = match function {
+        Some(function) => match apply(function, vec![item.clone()], &item.1)? {
+            Some(e) => e,
+            None => return Ok(None),
+        },
+        None => item.0.clone(),
+    };
+
+    match result {
+        Value::Bool(e) => Ok(Some(e)),
+        _ => Err(Error::TypeError {
+            expected: ValueType::Bool.into(),
+            got: SpannedValue(result, item.1.clone()),
+            context: TypeErrorCtx::Condition,
+        }),
+    }
+}
+
+fn map(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("map", &args, 2, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = function(&args[1], 1)?;
+    let mut out = Vec::new();
+
+    for item in items {
+        match apply(&function, vec![item.clone()], &item.1)? {
+            Some(e) => out.push(SpannedValue(e, item.1)),
+            None => return Ok(Value::None),
+        }
+    }
+
+    Ok(Value::Array(out))
+}
+
+fn filter(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("filter", &args, 2, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = function(&args[1], 1)?;
+    let mut out = Vec::new();
+
+    for item in items {
+        match test(Some(&function), &item)? {
+            Some(true) => out.push(item),
+            Some(false) => {}
+            None => return Ok(Value::None),
+        }
+    }
+
+    Ok(Value::Array(out))
+}
+
+fn fold_items(
+    function: &Function,
+    first: SpannedValue,
+    rest: Vec<SpannedValue>,
+) -> Result<Value, Error> {
+    let mut acc = first;
+
+    for item in rest {
+        let span = item.1.clone();
+
+        match apply(function, vec![acc, item], &span)? {
+            Some(e) => acc = SpannedValue(e, span),
+            None => return Ok(Value::None),
+        }
+    }
+
+    Ok(acc.0)
+}
+
+// `fold(items, start, |total, item| ..)`
+fn fold(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("fold", &args, 3, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = function(&args[2], 2)?;
+
+    fold_items(&function, args[1].clone(), items)
+}
+
+// like `fold`, starting from the first item, and `null` for an empty array
+fn reduce(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity("reduce", &args, 2, &span)?;
+
+    let mut items = array(&args[0], 0)?.into_iter();
+    let function = function(&args[1], 1)?;
+
+    match items.next() {
+        Some(first) => fold_items(&function, first, items.collect()),
+        None => Ok(Value::Null),
+    }
+}
+
+// without a function, the items have to be bools themselves
+fn any(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity_between("any", &args, 1, 2, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = args.get(1).map(|arg| function(arg, 1)).transpose()?;
+
+    for item in items {
+        match test(function.as_ref(), &item)? {
+            Some(true) => return Ok(Value::Bool(true)),
+            Some(false) => {}
+            None => return Ok(Value::None),
+        }
+    }
+
+    Ok(Value::Bool(false))
+}
+
+fn all(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity_between("all", &args, 1, 2, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = args.get(1).map(|arg| function(arg, 1)).transpose()?;
+
+    for item in items {
+        match test(function.as_ref(), &item)? {
+            Some(true) => {}
+            Some(false) => return Ok(Value::Bool(false)),
+            None => return Ok(Value::None),
+        }
+    }
+
+    Ok(Value::Bool(true))
+}
+
+fn count(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity_between("count", &args, 1, 2, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = args.get(1).map(|arg| function(arg, 1)).transpose()?;
+    let mut total = 0;
+
+    for item in items {
+        match test(function.as_ref(), &item)? {
+            Some(true) => total += 1,
+            Some(false) => {}
+            None => return Ok(Value::None),
+        }
+    }
+
+    Ok(Value::Int(total))
+}
+
+// sorts numbers or strings, optionally by a key given by `|item| ..`
+fn sort(args: Vec<SpannedValue>, span: Span) -> Result<Value, Error> {
+    arity_between("sort", &args, 1, 2, &span)?;
+
+    let items = array(&args[0], 0)?;
+    let function = args.get(1).map(|arg| function(arg, 1)).transpose()?;
+    let mut keyed = Vec::new();
+
+    for item in items {
+        let key = match &function {
+            Some(function) => match apply(function, vec![item.clone()], &item.1)? {
+                Some(e) => SpannedValue(e, item.1.clone()),
+                None => return Ok(Value::None),
+            },
+            None => item.clone(),
+        };
+
+        keyed.push((key, item));
+    }
+
+    // every key has to be the same kind of thing to be comparable
+    let expected = match keyed.first() {
+        Some((SpannedValue(Value::String(_), _), _)) => ValueType::String,
+        _ => ValueType::Num,
+    };
+
+    for (key, _) in &keyed {
+        if !expected.accepts(&key.0) {
+            return Err(Error::TypeError {
+                expected: expected.into(),
+                got: key.clone(),
+                context: TypeErrorCtx::Argument { index: 0 },
+            });
+        }
+    }
+
+    keyed.sort_by(|(a, _), (b, _)| match (&a.0, &b.0) {
+        (Value::Int(a), Value::Int(b)) => a.cmp(b),
+        (a, b) if a.as_num().is_some() && b.as_num().is_some() => a
+            .as_num()
+            .partial_cmp(&b.as_num())
+            .unwrap_or(Ordering::Equal),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        _ => Ordering::Equal,
+    });
+
+    Ok(Value::Array(
+        keyed.into_iter().map(|(_, item)| item).collect(),
+    ))
+}
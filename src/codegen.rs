@@ -1,4 +1,7 @@
-use crate::ast::{Expr, InfixOp, Literal, Spanned};
+use crate::{
+    ast::{Annotations, Expr, FormatPart, InfixOp, Literal, Modifiers, Spanned},
+    format::FormatSpec,
+};
 
 impl From<InfixOp> for String {
     fn from(f: InfixOp) -> Self {
@@ -6,6 +9,9 @@ impl From<InfixOp> for String {
             InfixOp::Pow => " ** ",
             InfixOp::Mul => " * ",
             InfixOp::Div => " / ",
+            InfixOp::FloorDiv => " div ",
+            InfixOp::RoundDiv => " rdiv ",
+            InfixOp::EvenDiv => " ediv ",
             InfixOp::Mod => " % ",
             InfixOp::Add => " + ",
             InfixOp::Sub => " - ",
@@ -20,6 +26,7 @@ impl From<InfixOp> for String {
             InfixOp::Or => " || ",
             InfixOp::Range => "..",
             InfixOp::IRange => "..=",
+            InfixOp::By => " by ",
         }
         .to_owned()
     }
@@ -28,10 +35,52 @@ impl From<InfixOp> for String {
 impl From<&Spanned> for String {
     fn from(input: &Spanned) -> Self {
         match input {
-            Spanned(Expr::Assign { names, value }, _) => {
-                let out = names.join(" = ");
+            // functions declared with `fn` keep that form, so they can still call themselves
+            Spanned(
+                Expr::Assign {
+                    value,
+                    modifiers,
+                    annotations,
+                    ..
+                },
+                _,
+            ) if matches!(value.0, Expr::Lambda { name: Some(_), .. }) => {
+                let (name, params, body) = match &value.0 {
+                    Expr::Lambda {
+                        name: Some(name),
+                        params,
+                        body,
+                    } => (name, params, body),
+                    _ => unreachable!(),
+                };
 
-                out + &String::from(*value.clone()) + ";"
+                annotations_str(annotations)
+                    + &modifiers_str(modifiers)
+                    + "fn "
+                    + name
+                    + "("
+                    + &params.join(", ")
+                    + ") = "
+                    + &String::from(*body.clone())
+                    + ";"
+            }
+            Spanned(
+                Expr::Assign {
+                    names,
+                    value,
+                    constraint,
+                    modifiers,
+                    annotations,
+                },
+                _,
+            ) => {
+                let out = annotations_str(annotations)
+                    + &modifiers_str(modifiers)
+                    + &names.join(" = ")
+                    + " = "
+                    + &String::from(*value.clone());
+
+                out + &constraint_str(constraint) + ";"
             }
             Spanned(Expr::Literal(value), _) => match value {
                 Literal::Array(inner) => {
@@ -39,6 +88,15 @@ impl From<&Spanned> for String {
 
                     "[".to_owned() + &inner.join(", ") + "]"
                 }
+                Literal::Record(fields) if fields.is_empty() => "{}".to_owned(),
+                Literal::Record(fields) => {
+                    let fields: Vec<String> = fields
+                        .iter()
+                        .map(|(name, value)| name.clone() + ": " + &String::from(value))
+                        .collect();
+
+                    "{ ".to_owned() + &fields.join(", ") + " }"
+                }
                 Literal::Bool(inner) => {
                     if *inner {
                         "true".to_owned()
@@ -46,9 +104,12 @@ impl From<&Spanned> for String {
                         "false".to_owned()
                     }
                 }
-                Literal::String(inner) => r#"""#.to_owned() + inner + r#"""#,
-                Literal::Num(inner) => inner.to_string(),
-                Literal::Null => "[NULL]".to_owned(),
+                Literal::String(inner) => r#"""#.to_owned() + &escape(inner) + r#"""#,
+                Literal::Int(inner) => inner.to_string(),
+                // `2.0` rather than `2`, so it isn't read back as an int
+                Literal::Num(inner) => format!("{:?}", inner),
+                Literal::Dice(inner) => inner.to_string(),
+                Literal::Null => "null".to_owned(),
             },
             Spanned(Expr::Ident(name), _) => name.clone(),
             Spanned(Expr::Not(inner), _) => {
@@ -56,33 +117,144 @@ impl From<&Spanned> for String {
 
                 "!(".to_owned() + &out + ")"
             }
+            Spanned(Expr::Neg(inner), _) => {
+                let out = String::from(*inner.clone());
+
+                "-(".to_owned() + &out + ")"
+            }
             Spanned(Expr::InfixOp(lhs, op, rhs), _) => {
                 let lhs_str = String::from(*lhs.clone());
-                let rhs_str = String::from(*rhs.clone());
                 let op_str = String::from(op.clone());
 
                 let lhs_str = match *lhs.clone() {
-                    Spanned(Expr::InfixOp(_, _, _), _) => "(".to_owned() + &lhs_str + ")",
+                    // `0..20 by 5` reads as a single range
+                    Spanned(Expr::InfixOp(_, InfixOp::Range | InfixOp::IRange, _), _)
+                        if *op == InfixOp::By =>
+                    {
+                        lhs_str
+                    }
+                    Spanned(Expr::InfixOp(_, _, _), _)
+                    | Spanned(Expr::Compare { .. }, _)
+                    | Spanned(Expr::Lambda { .. }, _) => "(".to_owned() + &lhs_str + ")",
+                    // `-2 ** 2` or `-(a) ** 2` would be read back as `-(2 ** 2)`
+                    Spanned(Expr::Neg(_) | Expr::Not(_), _) if *op == InfixOp::Pow => {
+                        "(".to_owned() + &lhs_str + ")"
+                    }
+                    Spanned(Expr::Literal(Literal::Int(e)), _) if *op == InfixOp::Pow && e < 0 => {
+                        "(".to_owned() + &lhs_str + ")"
+                    }
+                    Spanned(Expr::Literal(Literal::Num(e)), _)
+                        if *op == InfixOp::Pow && e.is_sign_negative() =>
+                    {
+                        "(".to_owned() + &lhs_str + ")"
+                    }
                     _ => lhs_str,
                 };
 
-                let rhs_str = match *rhs.clone() {
-                    Spanned(Expr::InfixOp(_, _, _), _) => "(".to_owned() + &rhs_str + ")",
-                    _ => rhs_str,
-                };
-
-                lhs_str + &op_str + &rhs_str
+                lhs_str + &op_str + &operand(rhs)
+            }
+            Spanned(Expr::Compare { first, rest }, _) => {
+                rest.iter().fold(operand(first), |out, (op, rhs)| {
+                    out + &String::from(*op) + &operand(rhs)
+                })
             }
             Spanned(Expr::Index(lhs, idx), _) => {
-                let lhs_str = String::from(*lhs.clone());
                 let idx_str = String::from(*idx.clone());
 
-                let lhs_str = match *lhs.clone() {
-                    Spanned(Expr::InfixOp(_, _, _), _) => "(".to_owned() + &lhs_str + ")",
-                    _ => lhs_str,
+                operand(lhs) + "[" + &idx_str + "]"
+            }
+            Spanned(Expr::Access(lhs, member), _) => {
+                let member_str = String::from(*member.clone());
+
+                operand(lhs) + "." + &member_str
+            }
+            Spanned(Expr::Call(callee, args), _) => {
+                let callee_str = operand(callee);
+                let args: Vec<String> = args.iter().map(String::from).collect();
+
+                callee_str + "(" + &args.join(", ") + ")"
+            }
+            Spanned(Expr::Let { .. }, _) => {
+                // nested bindings are written as one block, the way they're parsed
+                let mut out = "{ ".to_owned();
+                let mut current = input;
+
+                while let Spanned(Expr::Let { name, value, body }, _) = current {
+                    out += &("let ".to_owned() + name + " = " + &String::from(&**value) + "; ");
+                    current = body;
+                }
+
+                out + &String::from(current) + " }"
+            }
+            Spanned(Expr::Match { value, arms }, _) => {
+                let arms: Vec<String> = arms
+                    .iter()
+                    .map(|(patterns, body)| {
+                        let patterns: Vec<String> = patterns.iter().map(String::from).collect();
+
+                        patterns.join(" | ") + " => " + &String::from(body)
+                    })
+                    .collect();
+
+                "match ".to_owned()
+                    + &String::from(*value.clone())
+                    + " { "
+                    + &arms.join(", ")
+                    + " }"
+            }
+            Spanned(Expr::Format(parts), _) => {
+                let parts: Vec<String> = parts
+                    .iter()
+                    .map(|part| match part {
+                        FormatPart::Text(text) => escape(text),
+                        FormatPart::Value(value, spec) if *spec == FormatSpec::default() => {
+                            "{".to_owned() + &String::from(value) + "}"
+                        }
+                        FormatPart::Value(value, spec) => {
+                            "{".to_owned() + &String::from(value) + ":" + &spec.to_string() + "}"
+                        }
+                    })
+                    .collect();
+
+                // strings inside interpolations are written with double quotes, so they can't end this one
+                let quote = if parts.concat().contains('"') {
+                    "'"
+                } else {
+                    r#"""#
+                };
+
+                quote.to_owned() + &parts.concat() + quote
+            }
+            Spanned(
+                Expr::Comprehension {
+                    item,
+                    var,
+                    source,
+                    condition,
+                },
+                _,
+            ) => {
+                let condition = match condition {
+                    Some(condition) => " if ".to_owned() + &String::from(*condition.clone()),
+                    None => String::new(),
                 };
 
-                lhs_str + "[" + &idx_str + "]"
+                "[".to_owned()
+                    + &String::from(*item.clone())
+                    + " for "
+                    + var
+                    + " in "
+                    + &String::from(*source.clone())
+                    + &condition
+                    + "]"
+            }
+            Spanned(Expr::Lambda { params, body, .. }, _) => {
+                "|".to_owned() + &params.join(", ") + "| " + &String::from(*body.clone())
+            }
+            Spanned(Expr::Macro(name, args), _) => {
+                let args: Vec<String> = args.iter().map(String::from).collect();
+
+                name.clone() + "!(" + &args.join(", ") + ")"
             }
             Spanned(Expr::Error, _) => "[ERROR]".to_owned(),
             Spanned(
@@ -111,10 +283,60 @@ impl From<&Spanned> for String {
 
                 "if ".to_owned() + &cond_str + " { " + &inner_str + " } else " + &other_str + " }"
             }
-            Spanned(Expr::Input(name, kind), _) => {
-                "input ".to_owned() + name + ": " + &kind.to_string() + ";"
+            Spanned(
+                Expr::Input {
+                    name,
+                    kind,
+                    default,
+                    constraint,
+                    modifiers,
+                    annotations,
+                },
+                _,
+            ) => {
+                let default = match default {
+                    Some(default) => " = ".to_owned() + &String::from(&**default),
+                    None => String::new(),
+                };
+
+                annotations_str(annotations)
+                    + &modifiers_str(modifiers)
+                    + "input "
+                    + name
+                    + ": "
+                    + &kind.to_string()
+                    + &default
+                    + &constraint_str(constraint)
+                    + ";"
+            }
+            Spanned(
+                Expr::Block {
+                    name,
+                    items,
+                    annotations,
+                },
+                _,
+            ) => {
+                let items: Vec<String> = items.iter().map(String::from).collect();
+
+                annotations_str(annotations) + name + " { " + &items.join(" ") + " }"
             }
-            _ => todo!(),
+            Spanned(Expr::Use { path, alias }, _) => {
+                "use \"".to_owned() + path + "\" as " + alias + ";"
+            }
+            Spanned(Expr::Template(items), _) => {
+                let items: Vec<String> = items.iter().map(String::from).collect();
+
+                "common! { ".to_owned() + &items.join(" ") + " }"
+            }
+            Spanned(
+                Expr::Member {
+                    name,
+                    modifiers,
+                    annotations,
+                },
+                _,
+            ) => annotations_str(annotations) + &modifiers_str(modifiers) + name + ";",
         }
     }
 }
@@ -124,3 +346,57 @@ impl From<Spanned> for String {
         (&input).into()
     }
 }
+
+// something used inside a bigger expression, in parens if it would otherwise be read back differently
+// a lambda's body goes as far right as it can, so it would take whatever comes after it
+fn operand(expr: &Spanned) -> String {
+    let out = String::from(expr);
+
+    match expr {
+        Spanned(Expr::InfixOp(_, _, _), _)
+        | Spanned(Expr::Compare { .. }, _)
+        | Spanned(Expr::Lambda { .. }, _) => "(".to_owned() + &out + ")",
+        _ => out,
+    }
+}
+
+// braces are doubled so they aren't read back as interpolations
+fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+fn annotations_str(annotations: &Annotations) -> String {
+    if annotations.is_empty() {
+        return String::new();
+    }
+
+    let annotations: Vec<String> = annotations
+        .iter()
+        .map(|(key, value)| {
+            key.clone() + "(" + &String::from(Spanned::from(Expr::Literal(value.clone()))) + ")"
+        })
+        .collect();
+
+    "#[".to_owned() + &annotations.join(", ") + "] "
+}
+
+fn modifiers_str(modifiers: &Modifiers) -> String {
+    let mut out = String::new();
+
+    if modifiers.public {
+        out += "pub ";
+    }
+
+    if modifiers.output {
+        out += "out ";
+    }
+
+    out
+}
+
+fn constraint_str(constraint: &Option<Box<Spanned>>) -> String {
+    match constraint {
+        Some(constraint) => " where ".to_owned() + &String::from(&**constraint),
+        None => String::new(),
+    }
+}
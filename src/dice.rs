@@ -0,0 +1,237 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use serde::{Serialize, Serializer};
+
+// a pool of dice plus a flat modifier, like 2d6+1d4+3
+// kept symbolic until it's explicitly rolled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dice {
+    // (count, sides), a negative count means those dice are subtracted
+    pub pool: Vec<(i64, u32)>,
+    pub modifier: i64,
+}
+
+// the most dice of one size a pool can hold, so rolling it never takes too long
+pub const MAX_DICE: i64 = 1000;
+
+// source of randomness for rolling, supplied by the caller so rolls can be seeded and reproduced
+pub trait DiceRng {
+    // a number between 1 and `sides`, inclusive
+    fn roll(&mut self, sides: u32) -> u32;
+}
+
+impl<F: FnMut(u32) -> u32> DiceRng for F {
+    fn roll(&mut self, sides: u32) -> u32 {
+        self(sides)
+    }
+}
+
+impl Dice {
+    pub fn new(count: i64, sides: u32) -> Self {
+        Self {
+            pool: vec![(count, sides)],
+            modifier: 0,
+        }
+    }
+
+    // `None` if the total doesn't fit in an `i64`, or there are more than `MAX_DICE` of one size to roll
+    pub fn roll(&self, rng: &mut impl DiceRng) -> Option<i64> {
+        let mut total = self.modifier;
+
+        for (count, sides) in &self.pool {
+            if count.unsigned_abs() > MAX_DICE as u64 {
+                return None;
+            }
+
+            let rolled: i64 = (0..count.abs()).map(|_| rng.roll(*sides) as i64).sum();
+
+            total = total.checked_add(rolled * count.signum())?;
+        }
+
+        Some(total)
+    }
+
+    pub fn min(&self) -> Option<i64> {
+        self.pool
+            .iter()
+            .try_fold(self.modifier, |total, (count, sides)| {
+                let lowest = if *count > 0 {
+                    *count
+                } else {
+                    count * *sides as i64
+                };
+
+                total.checked_add(lowest)
+            })
+    }
+
+    pub fn max(&self) -> Option<i64> {
+        self.pool
+            .iter()
+            .try_fold(self.modifier, |total, (count, sides)| {
+                let highest = if *count > 0 {
+                    count * *sides as i64
+                } else {
+                    *count
+                };
+
+                total.checked_add(highest)
+            })
+    }
+
+    pub fn add_flat(mut self, modifier: i64) -> Option<Dice> {
+        self.modifier = self.modifier.checked_add(modifier)?;
+
+        Some(self)
+    }
+
+    // subtracts every die instead of adding it, and flips the modifier
+    pub fn negate(mut self) -> Option<Dice> {
+        for die in self.pool.iter_mut() {
+            die.0 = die.0.checked_neg()?;
+        }
+
+        self.modifier = self.modifier.checked_neg()?;
+
+        Some(self)
+    }
+
+    // dice of the same size are merged together, so 1d6 + 1d6 is 2d6
+    // `None` if the modifier overflows or there end up being more than `MAX_DICE` of one size
+    pub fn checked_add(mut self, other: Dice) -> Option<Dice> {
+        for (count, sides) in other.pool {
+            match self.pool.iter_mut().find(|(_, e)| *e == sides) {
+                Some(die) => die.0 = die.0.checked_add(count)?,
+                None => self.pool.push((count, sides)),
+            }
+        }
+
+        if self
+            .pool
+            .iter()
+            .any(|(count, _)| count.unsigned_abs() > MAX_DICE as u64)
+        {
+            return None;
+        }
+
+        self.pool.retain(|(count, _)| *count != 0);
+        self.modifier = self.modifier.checked_add(other.modifier)?;
+
+        Some(self)
+    }
+}
+
+impl Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+
+        for (count, sides) in &self.pool {
+            if *count < 0 {
+                write!(f, "-")?;
+            } else if !first {
+                write!(f, "+")?;
+            }
+
+            write!(f, "{}d{}", count.abs(), sides)?;
+            first = false;
+        }
+
+        if first {
+            write!(f, "{}", self.modifier)
+        } else if self.modifier > 0 {
+            write!(f, "+{}", self.modifier)
+        } else if self.modifier < 0 {
+            write!(f, "{}", self.modifier)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// parses a single die literal like `2d6`
+impl FromStr for Dice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (count, sides) = s
+            .split_once('d')
+            .ok_or_else(|| format!("`{}` is not a dice literal", s))?;
+
+        let count = count
+            .parse::<i64>()
+            .map_err(|_| format!("`{}` is not a valid number of dice", count))?;
+        let sides = sides
+            .parse::<u32>()
+            .map_err(|_| format!("`{}` is not a valid number of sides", sides))?;
+
+        if sides == 0 {
+            return Err("Dice need at least one side".to_owned());
+        }
+
+        if count.abs() > MAX_DICE {
+            return Err(format!("At most {} dice can be rolled at once", MAX_DICE));
+        }
+
+        Ok(Self::new(count, sides))
+    }
+}
+
+// dice serialize the same way they're displayed, e.g. "2d6+3"
+impl Serialize for Dice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Dice, MAX_DICE};
+
+    #[test]
+    fn dice_display() {
+        let dice = Dice::new(2, 6).checked_add(Dice::new(1, 4)).unwrap();
+
+        assert_eq!(dice.add_flat(3).unwrap().to_string(), "2d6+1d4+3");
+        assert_eq!(Dice::new(1, 20).add_flat(-1).unwrap().to_string(), "1d20-1");
+        assert_eq!(
+            Dice::new(1, 8)
+                .checked_add(Dice::new(-1, 4))
+                .unwrap()
+                .to_string(),
+            "1d8-1d4"
+        );
+    }
+
+    #[test]
+    fn dice_merge() {
+        let dice = Dice::new(2, 6).checked_add(Dice::new(1, 6));
+
+        assert_eq!(dice, Some(Dice::new(3, 6)));
+        assert_eq!(Dice::new(1, 12).negate().unwrap().to_string(), "-1d12");
+        assert_eq!(Dice::new(MAX_DICE, 6).checked_add(Dice::new(1, 6)), None);
+        assert_eq!(
+            Dice::new(1, 6).add_flat(i64::MAX).unwrap().add_flat(1),
+            None
+        );
+    }
+
+    #[test]
+    fn dice_roll_seeded() {
+        let dice = Dice::new(2, 6).add_flat(3).unwrap();
+        let mut rolls = [4, 5].into_iter();
+
+        assert_eq!(dice.roll(&mut |_| rolls.next().unwrap()), Some(12));
+        assert_eq!(dice.min(), Some(5));
+        assert_eq!(dice.max(), Some(15));
+    }
+
+    #[test]
+    fn dice_parse() {
+        assert_eq!("3d8".parse::<Dice>(), Ok(Dice::new(3, 8)));
+        assert!("3d0".parse::<Dice>().is_err());
+        assert!("9223372036854775807d6".parse::<Dice>().is_err());
+    }
+}
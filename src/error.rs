@@ -1,15 +1,16 @@
-use std::ops::Range;
-
-use ariadne::{ColorGenerator, Fmt, Label, Report, Source};
+use ariadne::{ColorGenerator, Fmt, Label};
 use chumsky::prelude::Simple;
 
 use crate::{
     ast::{InfixOp, Span, Token},
+    function::MAX_CALL_DEPTH,
     interpreter::SpannedIdent,
-    value::{SpannedValue, ValueType},
+    range::IntRange,
+    sources::SourceMap,
+    value::{SpannedValue, ValueType, MAX_ITEMS},
 };
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum TypeErrorCtx {
     // can't perform <op> on <lhs>
     // for when <lhs> is an invalid type for <op> in any use case
@@ -18,12 +19,29 @@ pub enum TypeErrorCtx {
     // for when <rhs> is an invalid type for <op> with <lhs>
     InfixOpRhs { lhs: ValueType, op: InfixOp },
     Not,
+    Neg,
     StringMul,
     Index,
     IndexOf,
+    // only records have fields
+    Access,
     AssignToAssign,
     Condition,
     Range,
+    // `by` takes how far apart the numbers in a range are
+    Step,
+    // value given for an input doesn't match its declared type
+    Input,
+    // argument <index> of a function call
+    Argument { index: usize },
+    // a `where` clause has to evaluate to a bool
+    Constraint,
+    // only functions can be called
+    Call,
+    // comprehensions go over the items of an array
+    Comprehension,
+    // signs, zero padding and decimal places in an interpolation
+    Format,
 }
 
 #[derive(Clone, Debug)]
@@ -54,11 +72,86 @@ pub enum Error {
         chain: Vec<SpannedIdent>,
     },
     RangeIndexError {
-        index: Range<isize>,
+        index: IntRange,
         len: usize,
         lspan: Span,
         rspan: Span,
     },
+    ArityError {
+        name: String,
+        expected: usize,
+        got: usize,
+        span: Span,
+    },
+    ConstraintError {
+        name: String,
+        value: SpannedValue,
+        constraint: Span,
+    },
+    MacroError {
+        name: String,
+        reason: String,
+        span: Span,
+    },
+    DeclarationError {
+        span: Span,
+    },
+    // something went wrong inside the body of a function
+    CallError {
+        name: String,
+        call: Span,
+        definition: Span,
+        inner: Vec<Error>,
+    },
+    CallDepthError {
+        name: String,
+        span: Span,
+    },
+    // a file given to `use` that couldn't be loaded
+    ImportError {
+        path: String,
+        reason: String,
+        span: Span,
+    },
+    // none of the arms of a `match` fit the value
+    MatchError {
+        value: SpannedValue,
+        span: Span,
+    },
+    // an arm of a `match` that's fully covered by an earlier one
+    UnreachableArmError {
+        span: Span,
+        covered_by: Span,
+    },
+    // integer arithmetic with a result that doesn't fit in an `Int`
+    OverflowError {
+        span: Span,
+    },
+    // dividing an `Int` by 0, which has no `Int` result
+    ZeroDivisionError {
+        span: Span,
+    },
+    // an array or string with more items than `value::MAX_ITEMS`, like a huge range used as an array
+    SizeError {
+        len: u128,
+        span: Span,
+    },
+    // `by` with a step that isn't positive, which would never get anywhere
+    StepError {
+        step: i64,
+        span: Span,
+    },
+    // a builtin like `min` given an empty array, which has no answer
+    EmptyError {
+        name: String,
+        span: Span,
+    },
+    // something went wrong for one of the items of a comprehension
+    ItemError {
+        name: String,
+        item: SpannedValue,
+        inner: Vec<Error>,
+    },
 }
 
 impl From<Error> for Vec<Error> {
@@ -68,16 +161,22 @@ impl From<Error> for Vec<Error> {
 }
 
 pub trait ChumskyAriadne {
-    fn display<'a>(&self, source_file: &'a str, source: &'a str, offset: usize);
+    fn display<'a>(&self, source_file: &'a str, source: &'a str, offset: usize) {
+        self.display_in(&SourceMap::new(source_file, source), offset)
+    }
+
+    // for sheets spread over several files, where spans point into the whole `SourceMap`
+    fn display_in(&self, sources: &SourceMap, offset: usize);
 }
 
 impl ChumskyAriadne for Simple<char> {
-    fn display<'a>(&self, source_file: &'a str, source: &'a str, offset: usize) {
-        Report::build(ariadne::ReportKind::Error, source, offset)
+    fn display_in(&self, sources: &SourceMap, offset: usize) {
+        sources
+            .report(offset)
             .with_code(1)
             .with_message("SyntaxError: Unexpected token")
             .with_label(
-                Label::new((source_file, self.span()))
+                Label::new(sources.locate(self.span()))
                     .with_message(format!("{}", self))
                     .with_color(ariadne::Color::Green),
             )
@@ -87,18 +186,19 @@ impl ChumskyAriadne for Simple<char> {
                 "No label".to_owned()
             })
             .finish()
-            .eprint((source_file, Source::from(source)))
+            .eprint(sources.cache())
             .unwrap();
     }
 }
 
 impl ChumskyAriadne for Simple<Token> {
-    fn display<'a>(&self, source_file: &'a str, source: &'a str, offset: usize) {
-        Report::build(ariadne::ReportKind::Error, source, offset)
+    fn display_in(&self, sources: &SourceMap, offset: usize) {
+        sources
+            .report(offset)
             .with_code(1)
             .with_message("SyntaxError: Unexpected token")
             .with_label(
-                Label::new((source_file, self.span()))
+                Label::new(sources.locate(self.span()))
                     .with_message(format!("{}", self))
                     .with_color(ariadne::Color::Green),
             )
@@ -108,13 +208,13 @@ impl ChumskyAriadne for Simple<Token> {
                 "No label".to_owned()
             })
             .finish()
-            .eprint((source_file, Source::from(source)))
+            .eprint(sources.cache())
             .unwrap();
     }
 }
 
 impl ChumskyAriadne for Error {
-    fn display<'a>(&self, source_file: &'a str, source: &'a str, offset: usize) {
+    fn display_in(&self, sources: &SourceMap, offset: usize) {
         let mut colors = ColorGenerator::new();
 
         match self {
@@ -144,11 +244,12 @@ impl ChumskyAriadne for Error {
                             )
                         };
 
-                        Report::build(ariadne::ReportKind::Error, source, offset)
+                        sources
+                            .report(offset)
                             .with_code(2)
                             .with_message("Incompatible types")
                             .with_label(
-                                Label::new((source_file, got.clone().1))
+                                Label::new(sources.locate(got.clone().1))
                                     .with_message(format!(
                                         "This is of type {}",
                                         got.0.get_type().to_string().fg(a)
@@ -157,16 +258,447 @@ impl ChumskyAriadne for Error {
                             )
                             .with_note(note)
                             .finish()
-                            .eprint((source_file, Source::from(source)))
+                            .eprint(sources.cache())
                             .unwrap();
                     }
-                    e => {
-                        println!("{:#?}", e);
-                        dbg!(self);
-                        todo!()
+                    TypeErrorCtx::Argument { index } => {
+                        let a = colors.next();
+                        let b = colors.next();
+
+                        sources
+                            .report(offset)
+                            .with_code(2)
+                            .with_message("Incompatible types")
+                            .with_label(
+                                Label::new(sources.locate(got.clone().1))
+                                    .with_message(format!(
+                                        "This is of type {}",
+                                        got.0.get_type().to_string().fg(a)
+                                    ))
+                                    .with_color(a),
+                            )
+                            .with_note(format!(
+                                "Argument {} must be of type {}",
+                                index + 1,
+                                stringify_expected(expected).fg(b)
+                            ))
+                            .finish()
+                            .eprint(sources.cache())
+                            .unwrap();
+                    }
+                    TypeErrorCtx::Not | TypeErrorCtx::Neg => {
+                        let a = colors.next();
+                        let b = colors.next();
+
+                        let op = match context {
+                            TypeErrorCtx::Not => "!",
+                            _ => "-",
+                        };
+
+                        sources
+                            .report(offset)
+                            .with_code(2)
+                            .with_message("Incompatible types")
+                            .with_label(
+                                Label::new(sources.locate(got.clone().1))
+                                    .with_message(format!(
+                                        "This is of type {}",
+                                        got.0.get_type().to_string().fg(a)
+                                    ))
+                                    .with_color(a),
+                            )
+                            .with_note(format!(
+                                "Prefix `{}` only accepts operands of type {}",
+                                op,
+                                stringify_expected(expected).fg(b)
+                            ))
+                            .finish()
+                            .eprint(sources.cache())
+                            .unwrap();
+                    }
+                    _ => {
+                        let a = colors.next();
+                        let b = colors.next();
+                        let expected = stringify_expected(expected).fg(b);
+
+                        let note = match context {
+                            TypeErrorCtx::InfixOpRhs { lhs, op } => format!(
+                                "Operator `{}` with a {} on the left only accepts {} on the right",
+                                op, lhs, expected
+                            ),
+                            TypeErrorCtx::StringMul => {
+                                format!("Strings can only be repeated by a whole number, of type {}", expected)
+                            }
+                            TypeErrorCtx::Index => format!("Indexes must be of type {}", expected),
+                            TypeErrorCtx::IndexOf => format!("Only values of type {} can be indexed", expected),
+                            TypeErrorCtx::Access => format!("Only values of type {} have fields", expected),
+                            TypeErrorCtx::Call => format!("Only values of type {} can be called", expected),
+                            TypeErrorCtx::Comprehension => format!("Only values of type {} can be looped over", expected),
+                            TypeErrorCtx::Format => format!("Only values of type {} can be formatted with a sign, zero padding or decimal places", expected),
+                            TypeErrorCtx::AssignToAssign => "Assignments can't be used as values".to_owned(),
+                            TypeErrorCtx::Condition => format!("Conditions must be of type {}", expected),
+                            TypeErrorCtx::Range => format!("The ends of a range must be of type {}", expected),
+                            TypeErrorCtx::Step => format!("Ranges can only step by a number of type {}", expected),
+                            TypeErrorCtx::Input => format!("This input only accepts values of type {}", expected),
+                            TypeErrorCtx::Constraint => {
+                                format!("`where` clauses must evaluate to a value of type {}", expected)
+                            }
+                            _ => format!("Expected a value of type {}", expected),
+                        };
+
+                        sources
+                            .report(offset)
+                            .with_code(2)
+                            .with_message("Incompatible types")
+                            .with_label(
+                                Label::new(sources.locate(got.clone().1))
+                                    .with_message(format!(
+                                        "This is of type {}",
+                                        got.0.get_type().to_string().fg(a)
+                                    ))
+                                    .with_color(a),
+                            )
+                            .with_note(note)
+                            .finish()
+                            .eprint(sources.cache())
+                            .unwrap();
                     }
                 }
             }
+            Self::DeclarationError { span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(10)
+                    .with_message("Declaration used as a value")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This declares {}", "something".fg(a)))
+                            .with_color(a),
+                    )
+                    .with_note("Blocks and templates don't have a value of their own")
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::CallError {
+                name,
+                call,
+                definition,
+                inner,
+            } => {
+                let a = colors.next();
+                let b = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(11)
+                    .with_message(format!("Error in call to `{}`", name))
+                    .with_label(
+                        Label::new(sources.locate(call.clone()))
+                            .with_message(format!(
+                                "`{}` is called {}",
+                                name.clone().fg(a),
+                                "here".fg(a)
+                            ))
+                            .with_color(a),
+                    )
+                    .with_label(
+                        Label::new(sources.locate(definition.clone()))
+                            .with_message(format!("It's defined {}", "here".fg(b)))
+                            .with_color(b),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap();
+
+                for err in inner {
+                    err.display_in(sources, offset);
+                }
+            }
+            Self::CallDepthError { name, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(12)
+                    .with_message("Too much recursion")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!(
+                                "This call to `{}` nests too deeply",
+                                name.clone().fg(a)
+                            ))
+                            .with_color(a),
+                    )
+                    .with_note(format!("Calls can only nest {} deep", MAX_CALL_DEPTH))
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::ImportError { path, reason, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(15)
+                    .with_message(format!("Can't use `{}`", path))
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(reason.fg(a))
+                            .with_color(a),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::MatchError { value, span } => {
+                let a = colors.next();
+                let b = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(13)
+                    .with_message("No arm matches")
+                    .with_label(
+                        Label::new(sources.locate(value.1.clone()))
+                            .with_message(format!("This is {}", value.0.to_string().fg(a)))
+                            .with_color(a),
+                    )
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!(
+                                "None of the arms of this {} fit it",
+                                "match".fg(b)
+                            ))
+                            .with_color(b),
+                    )
+                    .with_note(format!(
+                        "Add a {} arm to handle everything else",
+                        "`_ =>`".fg(b)
+                    ))
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::UnreachableArmError { span, covered_by } => {
+                let a = colors.next();
+                let b = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(14)
+                    .with_message("Unreachable arm")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This pattern can never {}", "match".fg(a)))
+                            .with_color(a),
+                    )
+                    .with_label(
+                        Label::new(sources.locate(covered_by.clone()))
+                            .with_message(format!(
+                                "Everything it matches is caught {}",
+                                "here".fg(b)
+                            ))
+                            .with_color(b),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::OverflowError { span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(16)
+                    .with_message("Integer overflow")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This is too big to fit in an {}", "Int".fg(a)))
+                            .with_color(a),
+                    )
+                    .with_note(format!(
+                        "Using a {} somewhere in it allows bigger but less exact results",
+                        "Num".fg(a)
+                    ))
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::ZeroDivisionError { span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(17)
+                    .with_message("Division by zero")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This is {}", "0".fg(a)))
+                            .with_color(a),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::SizeError { len, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(18)
+                    .with_message("Value too big")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This would have {} items", len.fg(a)))
+                            .with_color(a),
+                    )
+                    .with_note(format!(
+                        "Arrays and strings can have at most {} items",
+                        MAX_ITEMS
+                    ))
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::StepError { step, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(19)
+                    .with_message("Invalid step")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This is {}", step.fg(a)))
+                            .with_color(a),
+                    )
+                    .with_note(
+                        "Ranges can only step by a positive number, use `10..0` to count down",
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::EmptyError { name, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(21)
+                    .with_message("Empty array")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This array is {}", "empty".fg(a)))
+                            .with_color(a),
+                    )
+                    .with_note(format!("`{}` needs at least one item", name))
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::ItemError { name, item, inner } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(20)
+                    .with_message("Error in comprehension")
+                    .with_label(
+                        Label::new(sources.locate(item.1.clone()))
+                            .with_message(format!(
+                                "`{}` is this {}",
+                                name.clone().fg(a),
+                                item.0.get_type().to_string().fg(a)
+                            ))
+                            .with_color(a),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap();
+
+                for err in inner {
+                    err.display_in(sources, offset);
+                }
+            }
+            Self::SyntaxError(e) => e.display_in(sources, offset),
+            Self::ParsingError(e) => e.display_in(sources, offset),
+            Self::ReferenceError { name, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(8)
+                    .with_message("Unknown name")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!(
+                                "`{}` isn't declared anywhere",
+                                name.clone().fg(a)
+                            ))
+                            .with_color(a),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::RecursionError { chain } => {
+                let mut report = sources
+                    .report(offset)
+                    .with_code(9)
+                    .with_message("Variable depends on itself");
+
+                for (idx, ident) in chain.iter().enumerate() {
+                    let a = colors.next();
+                    let next = &chain[(idx + 1) % chain.len()];
+
+                    report = report.with_label(
+                        Label::new(sources.locate(ident.span.clone()))
+                            .with_message(format!(
+                                "`{}` depends on `{}`",
+                                ident.name.clone().fg(a),
+                                next.name
+                            ))
+                            .with_color(a),
+                    );
+                }
+
+                report
+                    .with_note("Variables can't depend on themselves, even through other variables")
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::RangeIndexError {
+                index,
+                len,
+                lspan: lhs,
+                rspan: rhs,
+            } => {
+                let a = colors.next();
+                let b = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(3)
+                    .with_message("Index out of bounds")
+                    .with_label(
+                        Label::new(sources.locate(lhs.clone()))
+                            .with_message(format!("This is of length {}", len.fg(a)))
+                            .with_color(a),
+                    )
+                    .with_label(
+                        Label::new(sources.locate(rhs.clone()))
+                            .with_message(format!("This is {}", index.to_string().fg(b)))
+                            .with_color(b),
+                    )
+                    .with_note("Ranges must fit within the bounds of the accessed sequence")
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
             Self::IndexError {
                 index,
                 len,
@@ -194,22 +726,23 @@ impl ChumskyAriadne for Error {
                     "Index must fit within the bounds of the accessed sequence".to_owned()
                 };
 
-                Report::build(ariadne::ReportKind::Error, source, offset)
+                sources
+                    .report(offset)
                     .with_code(3)
                     .with_message("Index out of bounds")
                     .with_label(
-                        Label::new((source_file, lhs.clone()))
+                        Label::new(sources.locate(lhs.clone()))
                             .with_message(format!("This is of length {}", len.fg(a)))
                             .with_color(a),
                     )
                     .with_label(
-                        Label::new((source_file, rhs.clone()))
+                        Label::new(sources.locate(rhs.clone()))
                             .with_message(format!("This is {}", index.fg(b)))
                             .with_color(b),
                     )
                     .with_note(note)
                     .finish()
-                    .eprint((source_file, Source::from(source)))
+                    .eprint(sources.cache())
                     .unwrap();
             }
             Self::ReassignError {
@@ -220,11 +753,12 @@ impl ChumskyAriadne for Error {
                 let a = colors.next();
                 let b = colors.next();
 
-                Report::build(ariadne::ReportKind::Error, source, offset)
+                sources
+                    .report(offset)
                     .with_code(4)
                     .with_message("Attempt to reassign variable")
                     .with_label(
-                        Label::new((source_file, old_span.clone()))
+                        Label::new(sources.locate(old_span.clone()))
                             .with_message(format!(
                                 "`{}` was first assigned {}",
                                 name.clone().fg(a),
@@ -233,29 +767,92 @@ impl ChumskyAriadne for Error {
                             .with_color(a),
                     )
                     .with_label(
-                        Label::new((source_file, new_span.clone()))
+                        Label::new(sources.locate(new_span.clone()))
                             .with_message(format!("It was then reassigned {}", "here".fg(b)))
                             .with_color(b),
                     )
                     .with_note("Variables may only be assigned once")
                     .finish()
-                    .eprint((source_file, Source::from(source)))
+                    .eprint(sources.cache())
                     .unwrap()
             }
-            e => {
-                dbg!(e);
-                todo!()
+            Self::ArityError {
+                name,
+                expected,
+                got,
+                span,
+            } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(5)
+                    .with_message("Wrong number of arguments")
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(format!("This passes {} arguments", got.fg(a)))
+                            .with_color(a),
+                    )
+                    .with_note(format!("`{}` takes {} arguments", name, expected))
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::ConstraintError {
+                name,
+                value,
+                constraint,
+            } => {
+                let a = colors.next();
+                let b = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(6)
+                    .with_message("Constraint not satisfied")
+                    .with_label(
+                        Label::new(sources.locate(value.1.clone()))
+                            .with_message(format!(
+                                "`{}` is given this {}",
+                                name.clone().fg(a),
+                                value.0.get_type().to_string().fg(a)
+                            ))
+                            .with_color(a),
+                    )
+                    .with_label(
+                        Label::new(sources.locate(constraint.clone()))
+                            .with_message(format!("It doesn't satisfy {}", "this".fg(b)))
+                            .with_color(b),
+                    )
+                    .with_note("`@` in a constraint stands for the value being checked")
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
+            }
+            Self::MacroError { name, reason, span } => {
+                let a = colors.next();
+
+                sources
+                    .report(offset)
+                    .with_code(7)
+                    .with_message(format!("Invalid use of `{}`", name))
+                    .with_label(
+                        Label::new(sources.locate(span.clone()))
+                            .with_message(reason.fg(a))
+                            .with_color(a),
+                    )
+                    .finish()
+                    .eprint(sources.cache())
+                    .unwrap()
             }
         }
     }
 }
 
 fn stringify_expected(expected: &Vec<ValueType>) -> String {
-    let out = expected
+    expected
         .iter()
         .map(|s| s.to_string())
         .collect::<Vec<String>>()
-        .join(", ");
-
-    out[..out.len() - 2].to_string()
+        .join(", ")
 }
@@ -1,11 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use crate::{
-    ast::{Expr, InfixOp, Literal, Spanned},
+    ast::{Expr, FormatPart, InfixOp, Literal, Span, Spanned},
+    builtins,
     error::{Error, TypeErrorCtx},
+    function::{CallGuard, Function},
+    interpreter::get_deps,
+    patterns::Pattern,
     value::{SpannedValue, Value, ValueType},
 };
 
+// when less stack than this is left, another `STACK_GROWTH` bytes are allocated before going deeper
+// so deep recursion runs into `MAX_CALL_DEPTH` instead of overflowing the stack
+const STACK_RED_ZONE: usize = 256 * 1024;
+const STACK_GROWTH: usize = 4 * 1024 * 1024;
+
 #[derive(Clone, Debug)]
 pub struct Output {
     pub value: SpannedValue,
@@ -13,11 +22,15 @@ pub struct Output {
     pub intermediates: HashMap<String, Expr>,
 }
 
+// either the output and any missing inputs, or the errors
+pub type Evaluated = Result<(SpannedValue, Vec<(String, ValueType)>), Vec<Error>>;
+
 // returns either output and missing inputs, or an error
-pub fn eval(
-    input: &Spanned,
-    vars: HashMap<String, Value>,
-) -> Result<(SpannedValue, Vec<(String, ValueType)>), Vec<Error>> {
+pub fn eval(input: &Spanned, vars: HashMap<String, Value>) -> Evaluated {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_GROWTH, || eval_expr(input, vars))
+}
+
+fn eval_expr(input: &Spanned, vars: HashMap<String, Value>) -> Evaluated {
     let mut errors: Vec<Error> = Vec::new();
 
     match input {
@@ -26,7 +39,7 @@ pub fn eval(
                 let mut errored = false;
                 let mut inputs = Vec::new();
 
-                let new = e
+                let new: Vec<SpannedValue> = e
                     .iter()
                     .map(|f| {
                         let evaluated = eval(f, vars.clone());
@@ -48,17 +61,53 @@ pub fn eval(
 
                 if errored {
                     Err(errors)
+                } else if new.iter().any(|item| is_pending(&item.0)) {
+                    Ok((SpannedValue(Value::None, span.clone()), inputs))
                 } else {
                     Ok((SpannedValue(Value::Array(new), span.clone()), inputs))
                 }
             }
+            Literal::Record(fields) => {
+                let mut inputs = Vec::new();
+                let mut record = BTreeMap::new();
+
+                for (name, field) in fields {
+                    match eval(field, vars.clone()) {
+                        Ok((value, field_inputs)) => {
+                            inputs.extend(field_inputs);
+                            record.insert(name.clone(), value);
+                        }
+                        Err(e) => errors.extend(e),
+                    }
+                }
+
+                if !errors.is_empty() {
+                    Err(errors)
+                } else if record.values().any(|field| is_pending(&field.0)) {
+                    Ok((SpannedValue(Value::None, span.clone()), inputs))
+                } else {
+                    Ok((SpannedValue(Value::Record(record), span.clone()), inputs))
+                }
+            }
             _ => Ok((
                 SpannedValue(Value::from(literal.clone()), span.clone()),
                 Vec::new(),
             )),
         },
-        Spanned(Expr::Assign { names, value }, span) => {
-            let evaluated = eval(value, vars);
+        Spanned(
+            Expr::Assign {
+                names,
+                value,
+                constraint,
+                ..
+            },
+            span,
+        ) => {
+            let evaluated = eval(value, vars.clone());
+
+            if let (Ok((spanned, _)), Some(constraint)) = (&evaluated, constraint) {
+                check_constraint(&names[0], spanned, constraint, vars)?;
+            }
 
             match evaluated {
                 Ok((spanned, inputs)) => Ok((
@@ -93,15 +142,23 @@ pub fn eval(
                 }
             };
 
+            // `and` and `or` leave the right side alone when the left side already decides it
+            if let InfixOp::And | InfixOp::Or = op {
+                if lhs == Value::Error {
+                    return Err(errors);
+                }
+
+                if is_pending(&lhs.0) {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs));
+                }
+
+                if lhs == Value::Bool(*op == InfixOp::Or) {
+                    return Ok((SpannedValue(lhs.0, span.clone()), inputs));
+                }
+            }
+
             let rhs = eval(rhs, vars);
             let rhs = match rhs {
-                Ok((SpannedValue(Value::Input(name, kind, value), _), _)) => match *value {
-                    Value::None => {
-                        inputs.push((name, kind));
-                        SpannedValue(Value::None, span.clone())
-                    }
-                    _ => SpannedValue(*value, span.clone()),
-                },
                 Ok(e) => {
                     inputs.extend(e.1);
                     e.0.clone()
@@ -117,10 +174,17 @@ pub fn eval(
                 return Err(errors);
             }
 
+            if is_pending(&lhs.0) || is_pending(&rhs.0) {
+                return Ok((SpannedValue(Value::None, span.clone()), inputs));
+            }
+
             let output = match op {
                 InfixOp::Pow => lhs.pow(rhs),
                 InfixOp::Mul => lhs.mul(rhs),
                 InfixOp::Div => lhs.div(rhs),
+                InfixOp::FloorDiv | InfixOp::RoundDiv | InfixOp::EvenDiv => {
+                    lhs.rounded_div(rhs, *op)
+                }
                 InfixOp::Mod => lhs.modulus(rhs),
                 InfixOp::Add => lhs.add(rhs),
                 InfixOp::Sub => lhs.sub(rhs),
@@ -135,6 +199,7 @@ pub fn eval(
                 InfixOp::In => lhs.contains(rhs),
                 InfixOp::Range => lhs.range(rhs),
                 InfixOp::IRange => lhs.irange(rhs),
+                InfixOp::By => lhs.step(rhs),
             };
 
             match output {
@@ -146,7 +211,46 @@ pub fn eval(
                 Ok(e) => Ok((SpannedValue(e, span.clone()), inputs)),
             }
         }
-        Spanned(Expr::Not(rhs), span) => {
+        Spanned(Expr::Compare { first, rest }, span) => {
+            let (mut lhs, mut inputs) = eval(first, vars.clone())?;
+
+            // each operand is compared with the one before it, stopping at the first comparison that's false
+            for (op, rhs) in rest {
+                if is_pending(&lhs.0) {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs));
+                }
+
+                let (rhs, rhs_inputs) = eval(rhs, vars.clone())?;
+                inputs.extend(rhs_inputs);
+
+                if is_pending(&rhs.0) {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs));
+                }
+
+                let compared = match op {
+                    InfixOp::Equals => lhs.equals(rhs.clone()),
+                    InfixOp::NotEquals => lhs.not_equals(rhs.clone()),
+                    InfixOp::Lt => lhs.lt(rhs.clone()),
+                    InfixOp::Gt => lhs.gt(rhs.clone()),
+                    InfixOp::Lte => lhs.lte(rhs.clone()),
+                    InfixOp::Gte => lhs.gte(rhs.clone()),
+                    _ => unreachable!("Only comparisons can be chained"),
+                };
+
+                match compared {
+                    Ok(Value::Bool(true)) => lhs = rhs,
+                    Ok(out) => return Ok((SpannedValue(out, span.clone()), inputs)),
+                    Err(e) => {
+                        errors.push(e);
+
+                        return Err(errors);
+                    }
+                }
+            }
+
+            Ok((SpannedValue(Value::Bool(true), span.clone()), inputs))
+        }
+        Spanned(Expr::Not(rhs), span) | Spanned(Expr::Neg(rhs), span) => {
             let mut inputs = Vec::new();
 
             let rhs = eval(rhs, vars);
@@ -162,7 +266,14 @@ pub fn eval(
                 }
             };
 
-            let output = rhs.not();
+            if is_pending(&rhs.0) {
+                return Ok((SpannedValue(Value::None, span.clone()), inputs));
+            }
+
+            let output = match input.0 {
+                Expr::Not(_) => rhs.not(),
+                _ => -rhs,
+            };
 
             match output {
                 Err(e) => {
@@ -206,6 +317,10 @@ pub fn eval(
                 return Err(errors);
             }
 
+            if is_pending(&lhs.0) || is_pending(&rhs.0) {
+                return Ok((SpannedValue(Value::None, span.clone()), inputs));
+            }
+
             let output = lhs.index(rhs);
 
             match output {
@@ -217,17 +332,364 @@ pub fn eval(
                 Ok(e) => Ok((SpannedValue(e, span.clone()), inputs)),
             }
         }
+        Spanned(Expr::Macro(name, _), span) => {
+            let err = Error::MacroError {
+                name: name.clone() + "!",
+                reason: "This can only be used inside a `common!` template".to_owned(),
+                span: span.clone(),
+            };
+            errors.push(err);
+
+            Err(errors)
+        }
+        Spanned(Expr::Call(callee, args), span) => {
+            // sheet variables win over builtins with the same name
+            let builtin = match callee.path() {
+                Some(name) if !vars.contains_key(&name) => builtins::get(&name),
+                _ => None,
+            };
+
+            let mut inputs = Vec::new();
+
+            let function = match builtin {
+                Some(_) => None,
+                None => match eval(callee, vars.clone()) {
+                    Ok((function, callee_inputs)) => {
+                        inputs.extend(callee_inputs);
+
+                        Some(function)
+                    }
+                    Err(e) => return Err(e),
+                },
+            };
+
+            let mut evaluated = Vec::new();
+
+            for arg in args {
+                match eval(arg, vars.clone()) {
+                    Ok((value, arg_inputs)) => {
+                        inputs.extend(arg_inputs);
+                        evaluated.push(value);
+                    }
+                    Err(e) => errors.extend(e),
+                }
+            }
+
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+
+            if evaluated.iter().any(|arg| is_pending(&arg.0))
+                || function
+                    .as_ref()
+                    .is_some_and(|function| is_pending(&function.0))
+            {
+                return Ok((SpannedValue(Value::None, span.clone()), inputs));
+            }
+
+            match (builtin, function) {
+                (Some(builtin), _) => match builtin(evaluated, span.clone()) {
+                    Err(e) => {
+                        errors.push(e);
+
+                        Err(errors)
+                    }
+                    Ok(e) => Ok((SpannedValue(e, span.clone()), inputs)),
+                },
+                (None, Some(SpannedValue(Value::Function(function), _))) => {
+                    match call(&function, evaluated, span) {
+                        Ok(e) => Ok((SpannedValue(e, span.clone()), inputs)),
+                        Err(e) => {
+                            errors.push(e);
+
+                            Err(errors)
+                        }
+                    }
+                }
+                (None, Some(other)) => {
+                    let err = Error::TypeError {
+                        expected: vec![ValueType::Function],
+                        got: other,
+                        context: TypeErrorCtx::Call,
+                    };
+                    errors.push(err);
+
+                    Err(errors)
+                }
+                (None, None) => unreachable!("The callee is evaluated when it isn't a builtin"),
+            }
+        }
+        Spanned(Expr::Let { name, value, body }, _) => {
+            let (value, mut inputs) = eval(value, vars.clone())?;
+
+            // shadows any variable with the same name, only inside the body
+            let mut vars = vars;
+            vars.insert(name.clone(), value.0);
+
+            let (out, body_inputs) = eval(body, vars)?;
+            inputs.extend(body_inputs);
+
+            Ok((out, inputs))
+        }
+        Spanned(Expr::Match { value, arms }, span) => {
+            let mut patterns: Vec<Vec<Pattern>> = Vec::new();
+            let mut pending = false;
+
+            for (arm, _) in arms {
+                let mut evaluated = Vec::new();
+
+                for pattern in arm {
+                    match eval_pattern(pattern, &vars) {
+                        Ok(Some(pattern)) => evaluated.push(pattern),
+                        Ok(None) => pending = true,
+                        Err(e) => errors.extend(e),
+                    }
+                }
+
+                patterns.push(evaluated);
+            }
+
+            // a pattern that only matches what an earlier one already does is a mistake
+            for (idx, arm) in patterns.iter().enumerate() {
+                for (pattern_idx, pattern) in arm.iter().enumerate() {
+                    let covering = patterns[..idx]
+                        .iter()
+                        .flatten()
+                        .chain(&arm[..pattern_idx])
+                        .find(|earlier| earlier.covers(pattern));
+
+                    if let Some(earlier) = covering {
+                        errors.push(Error::UnreachableArmError {
+                            span: pattern.span().clone(),
+                            covered_by: earlier.span().clone(),
+                        });
+                    }
+                }
+            }
+
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+
+            let (value, inputs) = eval(value, vars.clone())?;
+
+            if pending || is_pending(&value.0) {
+                return Ok((SpannedValue(Value::None, span.clone()), inputs));
+            }
+
+            for (arm, (_, body)) in patterns.iter().zip(arms) {
+                if arm.iter().any(|pattern| pattern.matches(&value.0)) {
+                    let (out, body_inputs) = eval(body, vars)?;
+
+                    return Ok((out, inputs.into_iter().chain(body_inputs).collect()));
+                }
+            }
+
+            errors.push(Error::MatchError {
+                value,
+                span: span.clone(),
+            });
+
+            Err(errors)
+        }
+        Spanned(Expr::Format(parts), span) => {
+            let mut out = String::new();
+            let mut inputs = Vec::new();
+
+            for part in parts {
+                let (value, spec) = match part {
+                    FormatPart::Text(text) => {
+                        out += text;
+                        continue;
+                    }
+                    FormatPart::Value(value, spec) => (value, spec),
+                };
+
+                let (value, value_inputs) = match eval(value, vars.clone()) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        errors.extend(e);
+                        continue;
+                    }
+                };
+                inputs.extend(value_inputs);
+
+                if is_pending(&value.0) {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs));
+                }
+
+                match spec.apply(&value.0) {
+                    Some(formatted) => out += &formatted,
+                    None => errors.push(Error::TypeError {
+                        expected: ValueType::Num.into(),
+                        got: value,
+                        context: TypeErrorCtx::Format,
+                    }),
+                }
+            }
+
+            if !errors.is_empty() {
+                return Err(errors);
+            }
+
+            Ok((SpannedValue(Value::String(out), span.clone()), inputs))
+        }
+        Spanned(
+            Expr::Comprehension {
+                item,
+                var,
+                source,
+                condition,
+            },
+            span,
+        ) => {
+            let (source, mut inputs) = eval(source, vars.clone())?;
+
+            let items = match source.0.items(&source.1) {
+                Some(Ok(items)) => items,
+                Some(Err(err)) => {
+                    errors.push(err);
+
+                    return Err(errors);
+                }
+                None if is_pending(&source.0) => {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs))
+                }
+                None => {
+                    let err = Error::TypeError {
+                        expected: vec![ValueType::Array, ValueType::Range],
+                        got: source,
+                        context: TypeErrorCtx::Comprehension,
+                    };
+                    errors.push(err);
+
+                    return Err(errors);
+                }
+            };
+
+            let mut out = Vec::new();
+
+            // errors for one item point at that item, since the variable alone doesn't say which
+            let item_error = |element: &SpannedValue, inner: Vec<Error>| {
+                vec![Error::ItemError {
+                    name: var.clone(),
+                    item: element.clone(),
+                    inner,
+                }]
+            };
+
+            for element in items {
+                let mut vars = vars.clone();
+                vars.insert(var.clone(), element.0.clone());
+
+                if let Some(condition) = condition {
+                    let (keep, condition_inputs) = eval(condition, vars.clone())
+                        .map_err(|inner| item_error(&element, inner))?;
+                    inputs.extend(condition_inputs);
+
+                    match keep.0 {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => continue,
+                        ref value if is_pending(value) => {
+                            return Ok((SpannedValue(Value::None, span.clone()), inputs))
+                        }
+                        _ => {
+                            let err = Error::TypeError {
+                                expected: ValueType::Bool.into(),
+                                got: keep,
+                                context: TypeErrorCtx::Condition,
+                            };
+                            errors.push(err);
+
+                            return Err(errors);
+                        }
+                    }
+                }
+
+                let (value, item_inputs) =
+                    eval(item, vars).map_err(|inner| item_error(&element, inner))?;
+                inputs.extend(item_inputs);
+
+                if is_pending(&value.0) {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs));
+                }
+
+                out.push(value);
+            }
+
+            Ok((SpannedValue(Value::Array(out), span.clone()), inputs))
+        }
+        Spanned(Expr::Lambda { name, params, body }, span) => {
+            // only the sheet variables the body actually uses are kept around
+            let captured = get_deps(input)
+                .into_iter()
+                .filter_map(|dep| vars.get(&dep).map(|value| (dep, value.clone())))
+                .collect();
+
+            let function = Function {
+                name: name.clone(),
+                params: params.clone(),
+                body: *body.clone(),
+                captured,
+                span: span.clone(),
+            };
+
+            Ok((
+                SpannedValue(Value::Function(function), span.clone()),
+                Vec::new(),
+            ))
+        }
+        Spanned(Expr::Access(lhs, member), span) => match input.path() {
+            Some(name) if vars.contains_key(&name) => {
+                eval(&Spanned(Expr::Ident(name), span.clone()), vars)
+            }
+            // not a declared name, so it has to be a field of a record
+            path => {
+                let (lhs, inputs) = match eval(lhs, vars) {
+                    Ok(e) => e,
+                    Err(e) => {
+                        match path {
+                            Some(name) => errors.push(Error::ReferenceError {
+                                name,
+                                span: span.clone(),
+                            }),
+                            None => errors.extend(e),
+                        }
+
+                        return Err(errors);
+                    }
+                };
+
+                if is_pending(&lhs.0) {
+                    return Ok((SpannedValue(Value::None, span.clone()), inputs));
+                }
+
+                let name = match &member.0 {
+                    Expr::Ident(name) => name.clone(),
+                    _ => String::from(&**member),
+                };
+
+                match lhs.field(&name, member.1.clone()) {
+                    Ok(e) => Ok((SpannedValue(e, span.clone()), inputs)),
+                    Err(e) => {
+                        errors.push(e);
+
+                        Err(errors)
+                    }
+                }
+            }
+        },
         Spanned(Expr::Ident(name), span) => match vars.get(name) {
             Some(out) => match out {
                 Value::Input(name, kind, value) => match **value {
                     Value::None => Ok((
                         SpannedValue(
-                            Value::Input(name.clone(), *kind, value.clone()),
+                            Value::Input(name.clone(), kind.clone(), value.clone()),
                             span.clone(),
                         ),
-                        vec![(name.clone(), *kind)],
+                        vec![(name.clone(), kind.clone())],
                     )),
-                    _ => Ok((SpannedValue(out.clone(), span.clone()), Vec::new())),
+                    _ => Ok((SpannedValue(*value.clone(), span.clone()), Vec::new())),
                 },
                 _ => Ok((SpannedValue(out.clone(), span.clone()), Vec::new())),
             },
@@ -251,8 +713,6 @@ pub fn eval(
         ) => {
             let evaluated = eval(condition, vars.clone())?;
 
-            dbg!(&evaluated);
-
             let out = match evaluated.0.clone() {
                 SpannedValue(Value::Bool(enter), _) => {
                     if enter {
@@ -261,30 +721,10 @@ pub fn eval(
                         eval(other, vars)
                     }
                 }
-                SpannedValue(Value::Input(name, ValueType::Bool, value), _) => match *value {
-                    Value::Bool(enter) => {
-                        if enter {
-                            eval(inner, vars)
-                        } else {
-                            eval(other, vars)
-                        }
-                    }
-                    Value::None => Ok((
-                        SpannedValue(Value::None, span.clone()),
-                        vec![(name, ValueType::Bool)],
-                    )),
-                    _ => {
-                        let err = Error::TypeError {
-                            expected: ValueType::Bool.into(),
-                            got: evaluated.0,
-                            context: TypeErrorCtx::Condition,
-                        };
-
-                        errors.push(err);
-
-                        return Err(errors);
-                    }
-                },
+                // can't pick a branch until the condition's inputs are given
+                SpannedValue(ref value, _) if is_pending(value) => {
+                    Ok((SpannedValue(Value::None, span.clone()), evaluated.1))
+                }
                 _ => {
                     let err = Error::TypeError {
                         expected: ValueType::Bool.into(),
@@ -300,58 +740,245 @@ pub fn eval(
 
             out
         }
-        Spanned(Expr::Input(name, kind), span) => {
-            dbg!(&name, &kind);
+        Spanned(
+            Expr::Input {
+                name,
+                kind,
+                default,
+                ..
+            },
+            span,
+        ) => {
+            // the default stands in for the input until it's given
+            let (value, inputs) = match default {
+                Some(default) => {
+                    let (value, inputs) = eval(default, vars)?;
+
+                    if is_pending(&value.0) {
+                        (Value::None, inputs)
+                    } else if kind.accepts(&value.0) {
+                        (value.0, inputs)
+                    } else {
+                        let err = Error::TypeError {
+                            expected: kind.clone().into(),
+                            got: value,
+                            context: TypeErrorCtx::Input,
+                        };
+                        errors.push(err);
+
+                        return Err(errors);
+                    }
+                }
+                None => (Value::None, Vec::new()),
+            };
 
             Ok((
                 SpannedValue(
-                    Value::Input(name.clone(), *kind, Box::new(Value::None)),
+                    Value::Input(name.clone(), kind.clone(), Box::new(value)),
                     span.clone(),
                 ),
-                Vec::new(),
+                inputs,
             ))
         }
-        _ => todo!(),
+        // left behind by the parser recovering from an error, which is already reported
+        Spanned(Expr::Error, span) => Ok((SpannedValue(Value::Error, span.clone()), Vec::new())),
+        Spanned(Expr::Block { .. }, span)
+        | Spanned(Expr::Template(_), span)
+        | Spanned(Expr::Member { .. }, span)
+        | Spanned(Expr::Use { .. }, span) => {
+            let err = Error::DeclarationError { span: span.clone() };
+            errors.push(err);
+
+            Err(errors)
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+// the values an arm of a `match` is compared against
+// `None` if the pattern depends on an input that hasn't been given yet
+fn eval_pattern(
+    pattern: &Spanned,
+    vars: &HashMap<String, Value>,
+) -> Result<Option<Pattern>, Vec<Error>> {
+    match pattern {
+        Spanned(Expr::Ident(name), span) if name == "_" => {
+            Ok(Some(Pattern::Wildcard(span.clone())))
+        }
+        Spanned(Expr::Literal(Literal::Array(items)), span) => {
+            let mut patterns = Vec::new();
 
-    use chumsky::{Parser, Stream};
+            for item in items {
+                match eval_pattern(item, vars)? {
+                    Some(pattern) => patterns.push(pattern),
+                    None => return Ok(None),
+                }
+            }
 
-    use crate::{ast::Spanned, error::Error, lexer::lexer, parser, value::ValueType};
+            Ok(Some(Pattern::Array(patterns, span.clone())))
+        }
+        _ => {
+            let (value, _) = eval(pattern, vars.clone())?;
 
-    use super::{SpannedValue, Value};
+            if is_pending(&value.0) {
+                Ok(None)
+            } else {
+                Ok(Some(Pattern::Value(value.0, value.1)))
+            }
+        }
+    }
+}
 
-    fn parse<'a>(input: &'a str) -> Vec<Spanned> {
-        let len = input.len();
+// runs the body of a function with its parameters bound to the given arguments
+pub(crate) fn call(
+    function: &Function,
+    args: Vec<SpannedValue>,
+    span: &Span,
+) -> Result<Value, Error> {
+    let name = function.name();
+
+    if args.len() != function.params.len() {
+        return Err(Error::ArityError {
+            name,
+            expected: function.params.len(),
+            got: args.len(),
+            span: span.clone(),
+        });
+    }
 
-        let lexed = lexer().parse(input).unwrap();
-        parser::parse()
-            .parse(Stream::from_iter(len..len + 1, lexed.into_iter()))
-            .unwrap()
+    let _guard = match CallGuard::enter() {
+        Some(guard) => guard,
+        None => {
+            return Err(Error::CallDepthError {
+                name,
+                span: span.clone(),
+            })
+        }
+    };
+
+    let mut vars = function.captured.clone();
+
+    // named functions can call themselves
+    if let Some(name) = &function.name {
+        vars.insert(name.clone(), Value::Function(function.clone()));
     }
 
-    fn evaluate(input: &Spanned) -> Result<SpannedValue, Vec<Error>> {
-        super::eval(input, HashMap::new()).map(|r| r.0)
+    for (param, arg) in function.params.iter().zip(args) {
+        vars.insert(param.clone(), arg.0);
     }
 
-    #[test]
-    fn evaluate_num() {
-        let parsed = &parse("12")[0];
-        let evaluated = evaluate(parsed).unwrap();
+    match eval(&function.body, vars) {
+        Ok((value, _)) => Ok(value.0),
+        Err(mut errs) => {
+            // errors from deeper in the same recursion already point at this function
+            let recursed = match errs.as_slice() {
+                [Error::CallDepthError { .. }] => true,
+                [Error::CallError { definition, .. }] => *definition == function.span,
+                _ => false,
+            };
 
-        assert_eq!(evaluated, Value::Num(12.0))
+            if recursed {
+                Err(errs.remove(0))
+            } else {
+                Err(Error::CallError {
+                    name,
+                    call: span.clone(),
+                    definition: function.span.clone(),
+                    inner: errs,
+                })
+            }
+        }
     }
+}
 
-    #[test]
-    fn evaluate_addition() {
-        let parsed = &parse("12 + 8")[0];
-        let evaluated = evaluate(parsed).unwrap();
-
-        assert_eq!(evaluated, Value::Num(20.0))
+// checks the `where` clause of a declaration, with `@` bound to the declared value
+pub fn check_constraint(
+    name: &str,
+    value: &SpannedValue,
+    constraint: &Spanned,
+    mut vars: HashMap<String, Value>,
+) -> Result<(), Vec<Error>> {
+    let value = match &value.0 {
+        Value::Input(_, _, inner) => SpannedValue(*inner.clone(), value.1.clone()),
+        _ => value.clone(),
+    };
+
+    if is_pending(&value.0) {
+        return Ok(());
+    }
+
+    vars.insert("@".to_owned(), value.0.clone());
+
+    let (checked, _) = eval(constraint, vars)?;
+
+    match checked.0 {
+        Value::Bool(true) => Ok(()),
+        Value::Bool(false) => Err(Error::ConstraintError {
+            name: name.to_owned(),
+            value,
+            constraint: constraint.1.clone(),
+        }
+        .into()),
+        // depends on something that isn't given yet
+        ref e if is_pending(e) => Ok(()),
+        _ => Err(Error::TypeError {
+            expected: ValueType::Bool.into(),
+            got: checked,
+            context: TypeErrorCtx::Constraint,
+        }
+        .into()),
+    }
+}
+
+// unset inputs, and anything computed from them, can't be evaluated yet
+pub(crate) fn is_pending(value: &Value) -> bool {
+    matches!(value, Value::None | Value::Input(_, _, _))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use chumsky::{Parser, Stream};
+
+    use crate::{
+        ast::{InfixOp, Spanned},
+        dice::Dice,
+        error::{Error, TypeErrorCtx},
+        lexer::lexer,
+        parser,
+        range::IntRange,
+        value::ValueType,
+    };
+
+    use super::{SpannedValue, Value};
+
+    fn parse<'a>(input: &'a str) -> Vec<Spanned> {
+        let len = input.len();
+
+        let lexed = lexer().parse(input).unwrap();
+        parser::parse()
+            .parse(Stream::from_iter(len..len + 1, lexed.into_iter()))
+            .unwrap()
+    }
+
+    fn evaluate(input: &Spanned) -> Result<SpannedValue, Vec<Error>> {
+        super::eval(input, HashMap::new()).map(|r| r.0)
+    }
+
+    #[test]
+    fn evaluate_num() {
+        let parsed = &parse("12")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(evaluated, Value::Int(12))
+    }
+
+    #[test]
+    fn evaluate_addition() {
+        let parsed = &parse("12 + 8")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(evaluated, Value::Int(20))
     }
 
     #[test]
@@ -359,7 +986,7 @@ mod tests {
         let parsed = &parse("12 + 8 * 3")[0];
         let evaluated = evaluate(parsed).unwrap();
 
-        assert_eq!(evaluated, Value::Num(36.0))
+        assert_eq!(evaluated, Value::Int(36))
     }
 
     #[test]
@@ -375,7 +1002,7 @@ mod tests {
         let parsed = &parse("10 + (30 - 5) * 3 ** 2")[0];
         let evaluated = evaluate(parsed).unwrap();
 
-        assert_eq!(evaluated, Value::Num(235.0))
+        assert_eq!(evaluated, Value::Int(235))
     }
 
     #[test]
@@ -394,6 +1021,22 @@ mod tests {
         assert!(evaluated.is_err())
     }
 
+    #[test]
+    fn evaluate_string_mul_too_big() {
+        let parsed = &parse("'a' * 100000000000000")[0];
+
+        assert!(matches!(
+            &evaluate(parsed).unwrap_err()[..],
+            [Error::SizeError {
+                len: 100000000000000,
+                ..
+            }]
+        ));
+
+        let parsed = &parse("'ab' * 500000")[0];
+        assert!(evaluate(parsed).is_ok());
+    }
+
     #[test]
     fn evaluate_string_mul_invalid_chain() {
         let parsed = &parse("'nice' * (3 * 'cool')")[0];
@@ -407,7 +1050,7 @@ mod tests {
         let parsed = &parse("[1, 2, 3, 4][3]")[0];
         let evaluated = evaluate(parsed).unwrap();
 
-        assert_eq!(evaluated, Value::Num(4.0))
+        assert_eq!(evaluated, Value::Int(4))
     }
 
     #[test]
@@ -418,6 +1061,54 @@ mod tests {
         assert_eq!(evaluated, Value::String("e".to_owned()))
     }
 
+    #[test]
+    fn evaluate_index_unicode() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(
+            evaluate_str("'Éowyn'[0]").unwrap(),
+            Value::String("É".to_owned())
+        );
+        assert_eq!(
+            evaluate_str("'Éowyn'[-1]").unwrap(),
+            Value::String("n".to_owned())
+        );
+        assert_eq!(
+            evaluate_str("'Éowyn'[0..3]").unwrap(),
+            Value::String("Éow".to_owned())
+        );
+        assert_eq!(
+            evaluate_str("'Éowyn'[1..5]").unwrap(),
+            Value::String("owyn".to_owned())
+        );
+        assert_eq!(
+            evaluate_str("'Éowyn'[2..=0]").unwrap(),
+            Value::String("woÉ".to_owned())
+        );
+        assert_eq!(
+            evaluate_str("'張三'[1]").unwrap(),
+            Value::String("三".to_owned())
+        );
+        assert_eq!(evaluate_str("len('張三')").unwrap(), Value::Int(2));
+        assert_eq!(
+            evaluate_str("[1, 2, 3, 4][3..1]").unwrap(),
+            evaluate_str("[4, 3]").unwrap()
+        );
+
+        let errs = evaluate_str("'張三'[2]").unwrap_err();
+        assert!(matches!(
+            &errs[..],
+            [Error::IndexError {
+                index: 2,
+                len: 2,
+                ..
+            }]
+        ));
+
+        let errs = evaluate_str("'張三'[0..=2]").unwrap_err();
+        assert!(matches!(&errs[..], [Error::RangeIndexError { len: 2, .. }]));
+    }
+
     #[test]
     fn evaluate_and_true() {
         let parsed = &parse("true and true")[0];
@@ -458,6 +1149,19 @@ mod tests {
         assert_eq!(evaluated, Value::Bool(true))
     }
 
+    #[test]
+    fn evaluate_short_circuit() {
+        // the right sides would fail if they were evaluated
+        let parsed = &parse("false and [1][5] == 1")[0];
+        assert_eq!(evaluate(parsed).unwrap(), Value::Bool(false));
+
+        let parsed = &parse("true or 1 / 0 == 1")[0];
+        assert_eq!(evaluate(parsed).unwrap(), Value::Bool(true));
+
+        let parsed = &parse("true and [1][5] == 1")[0];
+        assert!(evaluate(parsed).is_err());
+    }
+
     #[test]
     fn evaluate_assign() {
         let parsed = &parse("nice = 'cool';")[0];
@@ -488,6 +1192,23 @@ mod tests {
         assert_eq!(evaluated, Value::Bool(false))
     }
 
+    #[test]
+    fn evaluate_equals_nested_numbers() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).unwrap();
+
+        assert_eq!(evaluate_str("3 == 3.0"), Value::Bool(true));
+        assert_eq!(evaluate_str("[1, [2]] == [1.0, [2.0]]"), Value::Bool(true));
+        assert_eq!(evaluate_str("{a: 1} == {a: 1.0}"), Value::Bool(true));
+        assert_eq!(evaluate_str("{a: 1} == {b: 1}"), Value::Bool(false));
+        assert_eq!(evaluate_str("[1] != [1.5]"), Value::Bool(true));
+        assert_eq!(evaluate_str("3 in [3.0]"), Value::Bool(true));
+        assert_eq!(evaluate_str("[1] in [[1.0], [2.0]]"), Value::Bool(true));
+        assert_eq!(
+            evaluate_str("match [1.0] { [1] => 'one', _ => 'other' }"),
+            Value::String("one".to_owned())
+        );
+    }
+
     #[test]
     fn evaluate_equals_fail() {
         let parsed = &parse("50 == [50]")[0];
@@ -542,7 +1263,7 @@ mod tests {
 
         assert_eq!(
             evaluated2,
-            Value::Assign(vec!["nice".to_owned()], Box::new(Value::Num(69.0)))
+            Value::Assign(vec!["nice".to_owned()], Box::new(Value::Int(69)))
         )
     }
 
@@ -555,7 +1276,7 @@ mod tests {
             evaluated,
             Value::Assign(
                 vec!["these".to_owned(), "are".to_owned(), "all".to_owned()],
-                Box::new(Value::Num(12.0))
+                Box::new(Value::Int(12))
             )
         )
     }
@@ -565,7 +1286,112 @@ mod tests {
         let parsed = &parse("0..5")[0];
         let evaluated = evaluate(parsed).unwrap();
 
-        assert_eq!(evaluated, Value::Range(0..5))
+        assert_eq!(evaluated, Value::Range(IntRange::new(0, 5, false)))
+    }
+
+    #[test]
+    fn evaluate_range_steps() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(evaluate_str("7 in 5..=10").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_str("10 in 5..10").unwrap(), Value::Bool(false));
+        assert_eq!(evaluate_str("15 in 0..20 by 5").unwrap(), Value::Bool(true));
+        assert_eq!(
+            evaluate_str("12 in 0..20 by 5").unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(evaluate_str("sum(1..=10)").unwrap(), Value::Int(55));
+        assert_eq!(
+            evaluate_str("array(0..20 by 5)").unwrap(),
+            evaluate_str("[0, 5, 10, 15]").unwrap()
+        );
+        assert_eq!(
+            evaluate_str("array(3..0)").unwrap(),
+            evaluate_str("[3, 2, 1]").unwrap()
+        );
+        assert_eq!(
+            evaluate_str("[x * 2 for x in 1..4]").unwrap(),
+            evaluate_str("[2, 4, 6]").unwrap()
+        );
+        assert_eq!(
+            evaluate_str("'abcdef'[0..6 by 2]").unwrap(),
+            Value::String("ace".to_owned())
+        );
+
+        assert_eq!(
+            evaluate_str(
+                "match 8 { 0..5 => 'low', 5..=10 by 2 => 'odd', 10..0 => 'high', _ => 'other' }"
+            )
+            .unwrap(),
+            Value::String("high".to_owned())
+        );
+
+        let errs = evaluate_str("10..0 by -3").unwrap_err();
+        assert!(matches!(&errs[..], [Error::StepError { step: -3, .. }]));
+
+        let errs = evaluate_str("0..10 by 1.5").unwrap_err();
+        assert!(matches!(
+            &errs[..],
+            [Error::TypeError {
+                context: TypeErrorCtx::Step,
+                ..
+            }]
+        ));
+
+        // numbers between the ends are in a range counting by 1, the same as when matching
+        assert_eq!(evaluate_str("1.5 in 1..3").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_str("1.5 in 1..3 by 1").unwrap(), Value::Bool(true));
+        assert_eq!(
+            evaluate_str("1.5 in 0..3 by 3").unwrap(),
+            Value::Bool(false)
+        );
+        assert_eq!(evaluate_str("3.0 in 0..4 by 3").unwrap(), Value::Bool(true));
+        assert_eq!(
+            evaluate_str("match 1.5 { 1..3 => true, _ => false }").unwrap(),
+            Value::Bool(true)
+        );
+    }
+
+    #[test]
+    fn evaluate_range_as_array() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(evaluate_str("len(1..=10 by 3)").unwrap(), Value::Int(4));
+        assert_eq!(evaluate_str("(1..=10 by 3)[1]").unwrap(), Value::Int(4));
+        assert_eq!(evaluate_str("(1..=10 by 3)[-1]").unwrap(), Value::Int(10));
+        assert_eq!(
+            evaluate_str("(1..=10 by 3)[1..3]").unwrap(),
+            evaluate_str("[4, 7]").unwrap()
+        );
+        assert!(evaluate_str("(1..=10 by 3)[4]").is_err());
+        assert_eq!(
+            evaluate_str("(0..4000000000)[3999999999]").unwrap(),
+            Value::Int(3999999999)
+        );
+
+        // too many numbers to hold as an array at once
+        for input in ["sum(0..4000000000)", "[x for x in 0..4000000000]"] {
+            let errs = evaluate_str(input).unwrap_err();
+            assert!(matches!(&errs[..], [Error::SizeError { .. }]));
+        }
+    }
+
+    #[test]
+    fn evaluate_chained_compare() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(evaluate_str("1 <= 5 <= 20").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_str("1 <= 25 <= 20").unwrap(), Value::Bool(false));
+        assert_eq!(evaluate_str("1 < 2 < 3 == 3").unwrap(), Value::Bool(true));
+        // stops at the first comparison that's false
+        assert_eq!(evaluate_str("5 < 3 < 'a'").unwrap(), Value::Bool(false));
+
+        // the error points at the comparison that failed
+        let errs = evaluate_str("1 <= 5 <= 'a'").unwrap_err();
+        match &errs[..] {
+            [Error::TypeError { got, .. }] => assert_eq!(got.1, 10..13),
+            errs => panic!("unexpected errors {:?}", errs),
+        }
     }
 
     #[test]
@@ -632,4 +1458,542 @@ mod tests {
             Value::Input("cool".to_owned(), ValueType::Bool, Box::new(Value::None))
         )
     }
+
+    #[test]
+    fn evaluate_input_default() {
+        let parsed = &parse("input name: String = 'nice';")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Input(
+                "name".to_owned(),
+                ValueType::String,
+                Box::new(Value::String("nice".to_owned()))
+            )
+        );
+
+        let parsed = &parse("input name: String = 12;")[0];
+        assert!(evaluate(parsed).is_err());
+    }
+
+    #[test]
+    fn evaluate_int() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(evaluate_str("7 * 3 - 1").unwrap(), Value::Int(20));
+        assert_eq!(evaluate_str("2 ** 10").unwrap(), Value::Int(1024));
+        // `/` is a `Num` whether or not it divides evenly
+        assert_eq!(evaluate_str("12 / 4").unwrap(), Value::Num(3.0));
+        assert_eq!(evaluate_str("10 / 4").unwrap(), Value::Num(2.5));
+        assert_eq!(evaluate_str("-1 / 2").unwrap(), Value::Num(-0.5));
+        assert_eq!(evaluate_str("12 div 4").unwrap(), Value::Int(3));
+        assert_eq!(evaluate_str("7 % 4").unwrap(), Value::Int(3));
+        assert_eq!(evaluate_str("1 + 0.5").unwrap(), Value::Num(1.5));
+        assert_eq!(evaluate_str("0.1 * 3 == 0.3").unwrap(), Value::Bool(false));
+        assert_eq!(evaluate_str("3 == 3.0").unwrap(), Value::Bool(true));
+        assert_eq!(evaluate_str("floor(2.9999999)").unwrap(), Value::Int(2));
+
+        let overflow = evaluate_str("9223372036854775807 + 1").unwrap_err();
+        assert!(matches!(&overflow[..], [Error::OverflowError { span }] if *span == (0..23)));
+
+        let zero = evaluate_str("4 % 0").unwrap_err();
+        assert!(matches!(&zero[..], [Error::ZeroDivisionError { span }] if *span == (4..5)));
+    }
+
+    #[test]
+    fn evaluate_rounded_div() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(evaluate_str("(9 - 10) div 2").unwrap(), Value::Int(-1));
+        assert_eq!(evaluate_str("7 div -2").unwrap(), Value::Int(-4));
+        assert_eq!(evaluate_str("5 rdiv 2").unwrap(), Value::Int(3));
+        assert_eq!(evaluate_str("-5 rdiv 2").unwrap(), Value::Int(-2));
+        assert_eq!(evaluate_str("5 ediv 2").unwrap(), Value::Int(2));
+        assert_eq!(evaluate_str("7 ediv 2").unwrap(), Value::Int(4));
+        assert_eq!(evaluate_str("7 ediv 3").unwrap(), Value::Int(2));
+        assert_eq!(evaluate_str("4.5 div 2").unwrap(), Value::Num(2.0));
+        assert_eq!(evaluate_str("1 + 9 div 2 * 2").unwrap(), Value::Int(9));
+
+        let zero = evaluate_str("1 rdiv 0").unwrap_err();
+        assert!(matches!(&zero[..], [Error::ZeroDivisionError { .. }]));
+    }
+
+    #[test]
+    fn evaluate_neg() {
+        let parsed = &parse("-(2 + 3) * -2 - -1")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(evaluated, Value::Int(11));
+
+        // `**` binds tighter than `-`
+        assert_eq!(evaluate(&parse("-2 ** 2")[0]).unwrap(), Value::Int(-4));
+        assert_eq!(evaluate(&parse("(-2) ** 2")[0]).unwrap(), Value::Int(4));
+        assert_eq!(
+            evaluate(&parse("-2..2 ** 2")[0]).unwrap(),
+            evaluate(&parse("-2..4")[0]).unwrap()
+        );
+
+        let printed = String::from(&parse("(-2) ** 2")[0]);
+        assert_eq!(evaluate(&parse(&printed)[0]).unwrap(), Value::Int(4));
+
+        let parsed = &parse("-'nice'")[0];
+
+        match evaluate(parsed) {
+            Err(errs) => assert!(matches!(
+                errs[0],
+                Error::TypeError {
+                    context: TypeErrorCtx::Neg,
+                    ..
+                }
+            )),
+            Ok(_) => panic!("expected a type error"),
+        }
+    }
+
+    #[test]
+    fn evaluate_not() {
+        let parsed = &parse("not 1 > 2 && !false")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(evaluated, Value::Bool(true))
+    }
+
+    #[test]
+    fn evaluate_not_equals() {
+        let parsed = &parse("[1 != 2, 'a' != 'a', null == null, 3 != null]")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Array(vec![
+                Value::Bool(true).into(),
+                Value::Bool(false).into(),
+                Value::Bool(true).into(),
+                Value::Bool(true).into()
+            ])
+        )
+    }
+
+    #[test]
+    fn evaluate_declaration_as_value() {
+        let parsed = &parse("scores { str = 10; }")[0];
+
+        assert!(matches!(
+            evaluate(parsed).unwrap_err()[0],
+            Error::DeclarationError { .. }
+        ));
+    }
+
+    #[test]
+    fn evaluate_record() {
+        let parsed = &parse(
+            "[{ name: 'Rope', weight: 10 }.weight, { a: 1 }['a'], { a: 1, b: 2 } == { b: 2, a: 1 }, 'b' in { a: 1 }]",
+        )[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Array(vec![
+                Value::Int(10).into(),
+                Value::Int(1).into(),
+                Value::Bool(true).into(),
+                Value::Bool(false).into()
+            ])
+        );
+
+        let parsed = &parse("{ a: 1 }.b")[0];
+        assert!(matches!(
+            evaluate(parsed).unwrap_err()[0],
+            Error::ReferenceError { .. }
+        ));
+    }
+
+    #[test]
+    fn evaluate_call() {
+        let parsed = &parse("clamp(max([3, 12, 7]) + floor(2.7), 0, 20)")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(evaluated, Value::Int(14))
+    }
+
+    #[test]
+    fn evaluate_call_variadic() {
+        let parsed = &parse("min(4, 2, 9) + sum(repeat(2, 3)) + len('nice')")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(evaluated, Value::Int(12));
+
+        let errs = evaluate(&parse("max([])")[0]).unwrap_err();
+        assert!(
+            matches!(&errs[..], [Error::EmptyError { name, span }] if name == "max" && *span == (4..6))
+        );
+
+        let errs = evaluate(&parse("min()")[0]).unwrap_err();
+        assert!(matches!(&errs[..], [Error::ArityError { got: 0, .. }]));
+    }
+
+    #[test]
+    fn evaluate_call_type_error() {
+        let parsed = &parse("round(1, 'nice')")[0];
+        assert!(evaluate(parsed).is_err());
+
+        let parsed = &parse("abs('nice')")[0];
+        let evaluated = evaluate(parsed);
+
+        match evaluated {
+            Err(errs) => match &errs[0] {
+                Error::TypeError {
+                    expected: _,
+                    got,
+                    context: TypeErrorCtx::Argument { index: 0 },
+                } => assert_eq!(got.1, 4..10),
+                e => panic!("expected an argument type error, got {:?}", e),
+            },
+            Ok(_) => panic!("expected an argument type error"),
+        }
+    }
+
+    #[test]
+    fn evaluate_call_unknown() {
+        let parsed = &parse("nice(12)")[0];
+        let evaluated = evaluate(parsed);
+
+        assert!(evaluated.is_err())
+    }
+
+    #[test]
+    fn evaluate_dice() {
+        let parsed = &parse("2d6 + 3 + 1d4 - 1")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Dice(
+                Dice::new(2, 6)
+                    .checked_add(Dice::new(1, 4))
+                    .and_then(|dice| dice.add_flat(2))
+                    .unwrap()
+            )
+        )
+    }
+
+    #[test]
+    fn evaluate_dice_sum() {
+        let parsed = &parse("sum(repeat(1d12, 3)) + 1d6 + 1d6 + 12")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Dice(
+                Dice::new(3, 12)
+                    .checked_add(Dice::new(2, 6))
+                    .and_then(|dice| dice.add_flat(12))
+                    .unwrap()
+            )
+        )
+    }
+
+    #[test]
+    fn evaluate_repeat() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).map(|value| value.0);
+
+        assert_eq!(
+            evaluate_str("repeat('a', 2)").unwrap(),
+            evaluate_str("['a', 'a']").unwrap()
+        );
+        assert!(evaluate_str("repeat(1, -1)").is_err());
+
+        let errs = evaluate_str("repeat(1, 9223372036854775807)").unwrap_err();
+        assert!(matches!(&errs[..], [Error::SizeError { .. }]));
+    }
+
+    #[test]
+    fn evaluate_dice_errors() {
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]);
+
+        // doubling a roll isn't the same as rolling twice as many dice
+        assert!(evaluate_str("2d6 * 2").is_err());
+
+        let errs = evaluate_str("1d6 + 9223372036854775807 + 1").unwrap_err();
+        assert!(matches!(&errs[..], [Error::OverflowError { .. }]));
+
+        let errs = evaluate_str("1.5 + 1d6").unwrap_err();
+        assert!(matches!(
+            &errs[..],
+            [Error::TypeError {
+                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Add },
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn evaluate_dice_fractional_modifier() {
+        let parsed = &parse("1d20 + 0.5")[0];
+        let evaluated = evaluate(parsed);
+
+        assert!(evaluated.is_err())
+    }
+
+    #[test]
+    fn evaluate_constraint() {
+        let parsed = &parse("nice = 12 where @ >= 10 && @ <= 20;")[0];
+        assert!(evaluate(parsed).is_ok());
+
+        let parsed = &parse("nice = 22 where @ >= 10 && @ <= 20;")[0];
+        let evaluated = evaluate(parsed);
+
+        match evaluated {
+            Err(errs) => match &errs[0] {
+                Error::ConstraintError {
+                    name,
+                    value,
+                    constraint,
+                } => {
+                    assert_eq!(name, "nice");
+                    assert_eq!(value, &Value::Int(22));
+                    assert_eq!(*constraint, 16..34);
+                }
+                e => panic!("expected a constraint error, got {:?}", e),
+            },
+            Ok(_) => panic!("expected a constraint error"),
+        }
+    }
+
+    #[test]
+    fn evaluate_constraint_not_bool() {
+        let parsed = &parse("nice = 12 where @ + 1;")[0];
+        let evaluated = evaluate(parsed);
+
+        assert!(evaluated.is_err())
+    }
+
+    #[test]
+    fn evaluate_lambda() {
+        let parsed = &parse("[(|a, b| a * b)(3, 4), (|| 'nice')(), (|x| len(x))([1, 2])]")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Array(vec![
+                Value::Int(12).into(),
+                Value::String("nice".to_owned()).into(),
+                Value::Int(2).into()
+            ])
+        );
+
+        let parsed = &parse("(|a, b| a)(1)")[0];
+        assert!(matches!(
+            evaluate(parsed).unwrap_err()[0],
+            Error::ArityError {
+                expected: 2,
+                got: 1,
+                ..
+            }
+        ));
+
+        let parsed = &parse("3(1)")[0];
+        assert!(matches!(
+            evaluate(parsed).unwrap_err()[0],
+            Error::TypeError {
+                context: TypeErrorCtx::Call,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn evaluate_lambda_error_in_body() {
+        let parsed = &parse("(|x| x + 'nice')(true)")[0];
+
+        match &evaluate(parsed).unwrap_err()[0] {
+            Error::CallError {
+                call,
+                definition,
+                inner,
+                ..
+            } => {
+                assert_eq!(call, &(1..22));
+                assert_eq!(definition, &(1..15));
+                assert!(matches!(inner[0], Error::TypeError { .. }));
+            }
+            e => panic!("expected a call error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn evaluate_higher_order() {
+        let parsed = &parse(
+            "[map([1, 2, 3], |x| x * 2), filter([1, -2, 3], |x| x > 0), fold([1, 2, 3], 10, |a, b| a + b), reduce([], |a, b| a), sort(['b', 'c', 'a']), sort([3, 1, 2], |x| -x)]",
+        )[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        let array =
+            |items: Vec<Value>| Value::Array(items.into_iter().map(SpannedValue::from).collect());
+
+        assert_eq!(
+            evaluated,
+            array(vec![
+                array(vec![Value::Int(2), Value::Int(4), Value::Int(6)]),
+                array(vec![Value::Int(1), Value::Int(3)]),
+                Value::Int(16),
+                Value::Null,
+                array(vec![
+                    Value::String("a".to_owned()),
+                    Value::String("b".to_owned()),
+                    Value::String("c".to_owned())
+                ]),
+                array(vec![Value::Int(3), Value::Int(2), Value::Int(1)]),
+            ])
+        );
+
+        let parsed =
+            &parse("[any([false, true]), all([1, 2], |x| x > 1), count([1, 2, 3], |x| x != 2)]")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            array(vec![Value::Bool(true), Value::Bool(false), Value::Int(2)])
+        );
+    }
+
+    #[test]
+    fn evaluate_higher_order_element_error() {
+        let parsed = &parse("map([1, 'nice'], |x| x - 1)")[0];
+
+        match &evaluate(parsed).unwrap_err()[0] {
+            Error::CallError { call, .. } => assert_eq!(call, &(8..14)),
+            e => panic!("expected a call error, got {:?}", e),
+        }
+
+        let parsed = &parse("filter([1, 2], |x| x)")[0];
+
+        match &evaluate(parsed).unwrap_err()[0] {
+            Error::TypeError {
+                got,
+                context: TypeErrorCtx::Condition,
+                ..
+            } => assert_eq!(got.1, 8..9),
+            e => panic!("expected a condition type error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn evaluate_comprehension() {
+        let parsed = &parse("[x * 2 for x in [1, -2, 3] if x > 0]")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Array(vec![Value::Int(2).into(), Value::Int(6).into()])
+        );
+
+        // an error for one item points at the item it happened for
+        let errs = evaluate(&parse("[10 / x for x in [1, 0]]")[0]).unwrap_err();
+
+        match &errs[..] {
+            [Error::ItemError { name, item, inner }] => {
+                assert_eq!(name, "x");
+                assert_eq!(item.1, 21..22);
+                assert!(matches!(&inner[..], [Error::ZeroDivisionError { .. }]));
+            }
+            _ => panic!("expected an item error, got {:?}", errs),
+        }
+    }
+
+    #[test]
+    fn evaluate_format() {
+        let parsed = &parse("\"STR {16} ({3:+}) [{[1, 2]}] {0.5:.2}\"")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::String("STR 16 (+3) [[1, 2]] 0.50".to_owned())
+        );
+
+        let parsed = &parse("\"{ {a: 1, b: '}}'}.b }{ [{x: 2}][0].x }\"")[0];
+        assert_eq!(evaluate(parsed).unwrap(), Value::String("}2".to_owned()));
+
+        let parsed = &parse("\"{'nice':+}\"")[0];
+        assert!(matches!(
+            evaluate(parsed).unwrap_err()[0],
+            Error::TypeError {
+                context: TypeErrorCtx::Format,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn evaluate_match() {
+        let parsed = &parse(
+            "[match 6 { 1..=4 => 2, 5..=8 => 3, _ => 4 }, match 'b' { 'a' | 'b' => 1, _ => 2 }, match [1, 2] { [_, 1] => 1, [1, _] => 2, _ => 3 }, match 4.5 { 1..5 => 'in', _ => 'out' }]",
+        )[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Array(vec![
+                Value::Int(3).into(),
+                Value::Int(1).into(),
+                Value::Int(2).into(),
+                Value::String("in".to_owned()).into()
+            ])
+        );
+    }
+
+    #[test]
+    fn evaluate_match_errors() {
+        let parsed = &parse("match 9 { 1..=4 => 2, 5..=8 => 3 }")[0];
+        assert!(matches!(
+            evaluate(parsed).unwrap_err()[0],
+            Error::MatchError { .. }
+        ));
+
+        let parsed = &parse("match 1 { 1..=8 => 2, 2..5 => 3, _ => 4, 7 => 5 }")[0];
+
+        match evaluate(parsed).unwrap_err().as_slice() {
+            [Error::UnreachableArmError { span, covered_by }, Error::UnreachableArmError { .. }] => {
+                assert_eq!(span, &(22..26));
+                assert_eq!(covered_by, &(10..15));
+            }
+            e => panic!("expected two unreachable arm errors, got {:?}", e),
+        }
+
+        // an inclusive range reaches further than an exclusive one with the same bounds
+        let parsed = &parse("match 1 { 1..4 => 2, 1..=4 => 3, _ => 4 }")[0];
+        assert!(evaluate(parsed).is_ok());
+    }
+
+    #[test]
+    fn evaluate_let() {
+        let parsed = &parse("{ let x = 2; let y = { let x = 10; x + 1 }; [x, y] }")[0];
+        let evaluated = evaluate(parsed).unwrap();
+
+        assert_eq!(
+            evaluated,
+            Value::Array(vec![Value::Int(2).into(), Value::Int(11).into()])
+        );
+
+        let parsed = &parse("if true { let x = 3; x * x } else { 0 }")[0];
+        assert_eq!(evaluate(parsed).unwrap(), Value::Int(9));
+
+        let evaluate_str = |input: &str| evaluate(&parse(input)[0]).unwrap();
+
+        assert_eq!(evaluate_str("let b = 2 in b * 3"), Value::Int(6));
+        assert_eq!(
+            evaluate_str("let a = 1 in let a = a + 1 in a * 10"),
+            Value::Int(20)
+        );
+        // `in` after the binding is membership again
+        assert_eq!(
+            evaluate_str("let xs = [1, 2] in 2 in xs and true"),
+            Value::Bool(true)
+        );
+        assert_eq!(
+            evaluate_str("let x = if false { 1 } else { 2 } in x + 1"),
+            Value::Int(3)
+        );
+    }
 }
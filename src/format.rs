@@ -0,0 +1,209 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use crate::value::Value;
+
+// what comes after the `:` in an interpolation like `{modifier:+}`
+// `[<|>][+][0][width][.precision]`, in that order
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FormatSpec {
+    pub align: Option<Align>,
+    pub sign: bool, // `+`, positive numbers get a sign too
+    pub zero: bool, // `0`, numbers are padded with zeros after the sign
+    pub width: Option<usize>,
+    pub precision: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+impl FormatSpec {
+    // whether this only makes sense for numbers
+    pub fn numeric(&self) -> bool {
+        self.sign || self.zero || self.precision.is_some()
+    }
+
+    // `None` if the value can't be formatted this way
+    pub fn apply(&self, value: &Value) -> Option<String> {
+        let (body, sign) = match value {
+            Value::Num(e) => {
+                let body = match self.precision {
+                    Some(precision) => format!("{:.*}", precision, e.abs()),
+                    None => e.abs().to_string(),
+                };
+
+                let sign = if e.is_sign_negative() && body.chars().any(|c| c != '0' && c != '.') {
+                    "-"
+                } else if self.sign {
+                    "+"
+                } else {
+                    ""
+                };
+
+                (body, sign)
+            }
+            // decimal places only make sense on floats
+            Value::Int(e) if self.precision.is_some() => return self.apply(&Value::Num(*e as f64)),
+            Value::Int(e) => {
+                let sign = if *e < 0 {
+                    "-"
+                } else if self.sign {
+                    "+"
+                } else {
+                    ""
+                };
+
+                (e.unsigned_abs().to_string(), sign)
+            }
+            Value::Input(_, _, inner) => return self.apply(inner),
+            _ if self.numeric() => return None,
+            _ => (value.to_string(), ""),
+        };
+
+        let len = body.chars().count() + sign.len();
+        let padding = self.width.unwrap_or(0).saturating_sub(len);
+
+        // numbers line up on the right unless asked otherwise, like everything else in a column
+        let align = self.align.unwrap_or(match value {
+            Value::Int(_) | Value::Num(_) => Align::Right,
+            _ => Align::Left,
+        });
+
+        let out = if self.zero && self.align.is_none() {
+            sign.to_owned() + &"0".repeat(padding) + &body
+        } else {
+            match align {
+                Align::Left => sign.to_owned() + &body + &" ".repeat(padding),
+                Align::Right => " ".repeat(padding) + sign + &body,
+            }
+        };
+
+        Some(out)
+    }
+}
+
+impl FromStr for FormatSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut spec = FormatSpec::default();
+        let mut rest = s;
+
+        if let Some(stripped) = rest.strip_prefix('<') {
+            spec.align = Some(Align::Left);
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('>') {
+            spec.align = Some(Align::Right);
+            rest = stripped;
+        }
+
+        if let Some(stripped) = rest.strip_prefix('+') {
+            spec.sign = true;
+            rest = stripped;
+        }
+
+        if let Some(stripped) = rest.strip_prefix('0') {
+            spec.zero = true;
+            rest = stripped;
+        }
+
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (rest, None),
+        };
+
+        if !width.is_empty() {
+            spec.width = Some(
+                width
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a valid width", width))?,
+            );
+        }
+
+        if let Some(precision) = precision {
+            spec.precision =
+                Some(precision.parse().map_err(|_| {
+                    format!("`{}` is not a valid number of decimal places", precision)
+                })?);
+        }
+
+        Ok(spec)
+    }
+}
+
+impl Display for FormatSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.align {
+            Some(Align::Left) => write!(f, "<")?,
+            Some(Align::Right) => write!(f, ">")?,
+            None => {}
+        }
+
+        if self.sign {
+            write!(f, "+")?;
+        }
+
+        if self.zero {
+            write!(f, "0")?;
+        }
+
+        if let Some(width) = self.width {
+            write!(f, "{}", width)?;
+        }
+
+        if let Some(precision) = self.precision {
+            write!(f, ".{}", precision)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::value::Value;
+
+    use super::{Align, FormatSpec};
+
+    #[test]
+    fn parse_spec() {
+        let spec: FormatSpec = ">+05.2".parse().unwrap();
+
+        assert_eq!(
+            spec,
+            FormatSpec {
+                align: Some(Align::Right),
+                sign: true,
+                zero: true,
+                width: Some(5),
+                precision: Some(2),
+            }
+        );
+        assert_eq!(spec.to_string(), ">+05.2");
+        assert!("+x".parse::<FormatSpec>().is_err());
+    }
+
+    #[test]
+    fn apply_spec() {
+        let apply = |spec: &str, value: Value| spec.parse::<FormatSpec>().unwrap().apply(&value);
+
+        assert_eq!(apply("+", Value::Int(3)).unwrap(), "+3");
+        assert_eq!(apply("03", Value::Int(-4)).unwrap(), "-04");
+        assert_eq!(apply(".1", Value::Int(2)).unwrap(), "2.0");
+        assert_eq!(apply("+", Value::Num(-1.0)).unwrap(), "-1");
+        assert_eq!(apply("+", Value::Num(0.0)).unwrap(), "+0");
+        assert_eq!(apply(".2", Value::Num(1.5)).unwrap(), "1.50");
+        assert_eq!(apply("+03", Value::Num(7.0)).unwrap(), "+07");
+        assert_eq!(apply("4", Value::Num(12.0)).unwrap(), "  12");
+        assert_eq!(
+            apply("6", Value::String("STR".to_owned())).unwrap(),
+            "STR   "
+        );
+        assert_eq!(apply("+", Value::String("STR".to_owned())), None);
+    }
+}
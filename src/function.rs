@@ -0,0 +1,70 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt::{self, Display},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{
+    ast::{Span, Spanned},
+    value::Value,
+};
+
+// how deep calls can nest before giving up, so runaway recursion is an error instead of a crash
+pub const MAX_CALL_DEPTH: usize = 64;
+
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// a lambda, or a function declared with `fn`, along with the sheet variables it uses
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub body: Spanned,
+    pub captured: HashMap<String, Value>,
+    pub span: Span,
+}
+
+impl Function {
+    pub fn name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| "lambda".to_owned())
+    }
+}
+
+// keeps track of one level of call depth for as long as it's alive
+pub(crate) struct CallGuard;
+
+impl CallGuard {
+    pub(crate) fn enter() -> Option<CallGuard> {
+        CALL_DEPTH.with(|depth| {
+            if depth.get() >= MAX_CALL_DEPTH {
+                None
+            } else {
+                depth.set(depth.get() + 1);
+                Some(CallGuard)
+            }
+        })
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        CALL_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "fn {}({})", self.name(), self.params.join(", "))
+    }
+}
+
+// functions serialize as their signature, e.g. "fn modifier(score)"
+impl Serialize for Function {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{
+    ast::{Expr, Span, Spanned},
+    error::Error,
+    interpreter::parse_source,
+    sources::SourceMap,
+};
+
+// reads the file at a path, relative to wherever the sheet is being run from
+type Loader<'a> = &'a mut dyn FnMut(&str) -> io::Result<String>;
+
+// the items of the file at `path`, with every `use` replaced by a block of the file it names
+pub(crate) fn load(
+    path: &str,
+    sources: &mut SourceMap,
+    loader: Loader,
+) -> Result<Vec<Spanned>, Vec<Error>> {
+    load_file(path, 0..0, sources, loader, &mut Vec::new())
+}
+
+// `use` needs to know where the sheet is to find other files
+pub(crate) fn reject(items: &[Spanned]) -> Result<(), Vec<Error>> {
+    let mut errs = Vec::new();
+
+    for item in items {
+        match item {
+            Spanned(Expr::Use { path, .. }, span) => errs.push(Error::ImportError {
+                path: path.clone(),
+                reason: "Only sheets read from a file can use other files".to_owned(),
+                span: span.clone(),
+            }),
+            Spanned(Expr::Block { items, .. }, _) => {
+                if let Err(e) = reject(items) {
+                    errs.extend(e);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if errs.is_empty() {
+        Ok(())
+    } else {
+        Err(errs)
+    }
+}
+
+// `stack` is every file currently being loaded, to catch files that end up using themselves
+fn load_file(
+    path: &str,
+    span: Span,
+    sources: &mut SourceMap,
+    loader: Loader,
+    stack: &mut Vec<String>,
+) -> Result<Vec<Spanned>, Vec<Error>> {
+    if let Some(idx) = stack.iter().position(|loading| loading == path) {
+        let mut chain = stack[idx..].to_vec();
+        chain.push(path.to_owned());
+
+        return Err(vec![Error::ImportError {
+            path: path.to_owned(),
+            reason: format!("This ends up using itself: `{}`", chain.join("` uses `")),
+            span,
+        }]);
+    }
+
+    let source = loader(path).map_err(|e| {
+        // the sheet itself couldn't be read, so there's no `use` to point at
+        // it goes in as an empty file instead, so the error is still labeled with its path
+        let span = if stack.is_empty() {
+            let offset = sources.add(path, "");
+            offset..offset
+        } else {
+            span
+        };
+
+        vec![Error::ImportError {
+            path: path.to_owned(),
+            reason: e.to_string(),
+            span,
+        }]
+    })?;
+
+    let offset = sources.add(path, &source);
+    let parsed = parse_source(&source, offset)?;
+
+    stack.push(path.to_owned());
+    let expanded = expand(&parsed, path, sources, loader, stack);
+    stack.pop();
+
+    expanded
+}
+
+fn expand(
+    items: &[Spanned],
+    path: &str,
+    sources: &mut SourceMap,
+    loader: Loader,
+    stack: &mut Vec<String>,
+) -> Result<Vec<Spanned>, Vec<Error>> {
+    let mut errs = Vec::new();
+    let mut out = Vec::new();
+
+    for item in items {
+        match item {
+            Spanned(Expr::Use { path: used, alias }, span) => {
+                let used = relative(path, used);
+
+                match load_file(&used, span.clone(), sources, loader, stack) {
+                    Ok(items) => {
+                        let block = Expr::Block {
+                            name: alias.clone(),
+                            items,
+                            annotations: Vec::new(),
+                        };
+
+                        out.push(Spanned(block, span.clone()));
+                    }
+                    Err(e) => errs.extend(e),
+                }
+            }
+            Spanned(
+                Expr::Block {
+                    name,
+                    items,
+                    annotations,
+                },
+                span,
+            ) => match expand(items, path, sources, loader, stack) {
+                Ok(items) => {
+                    let block = Expr::Block {
+                        name: name.clone(),
+                        items,
+                        annotations: annotations.clone(),
+                    };
+
+                    out.push(Spanned(block, span.clone()));
+                }
+                Err(e) => errs.extend(e),
+            },
+            _ => out.push(item.clone()),
+        }
+    }
+
+    if errs.is_empty() {
+        Ok(out)
+    } else {
+        Err(errs)
+    }
+}
+
+// paths in `use` are relative to the file they're written in
+fn relative(from: &str, path: &str) -> String {
+    let joined = match Path::new(from).parent() {
+        Some(dir) => dir.join(path),
+        None => PathBuf::from(path),
+    };
+
+    // `rules/../core.plm` and `core.plm` are the same file
+    let mut normalized = PathBuf::new();
+
+    for component in joined.components() {
+        match component {
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+
+    normalized.to_string_lossy().replace('\\', "/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::relative;
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(relative("sheet.plm", "rules/core.plm"), "rules/core.plm");
+        assert_eq!(relative("rules/core.plm", "dice.plm"), "rules/dice.plm");
+        assert_eq!(relative("rules/core.plm", "../sheet.plm"), "sheet.plm");
+        assert_eq!(relative("./a/b.plm", "./c.plm"), "a/c.plm");
+    }
+}
@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use chumsky::{Parser, Stream};
 
-use crate::{error::Error, interpreter::VarStore, lexer, parser};
+use crate::{
+    error::{Error, TypeErrorCtx},
+    eval::{check_constraint, eval},
+    interpreter::{get_read_deps, InputInfo, InputState, VarStore},
+    lexer, parser,
+    value::{SpannedValue, Value},
+};
 
 impl VarStore {
     pub fn new() -> Self {
@@ -14,11 +20,105 @@ impl VarStore {
             source: HashMap::new(),
             cached: HashMap::new(),
             intermediate: HashMap::new(),
+            exprs: HashMap::new(),
+            constraints: HashMap::new(),
+            set: HashSet::new(),
+            live: HashMap::new(),
+            modifiers: HashMap::new(),
+            annotations: HashMap::new(),
         }
     }
 
-    pub fn set_input(name: &str, value: &str) -> Result<Self, Vec<Error>> {
-        let len = value.len();
+    // every input along with where its value is coming from, sorted by name
+    pub fn get_inputs(&self) -> Vec<InputInfo> {
+        let needed = self.needed();
+        let mut inputs: Vec<InputInfo> = self
+            .values
+            .iter()
+            .filter_map(|(name, value)| match value {
+                Value::Input(_, kind, value) => {
+                    let state = if self.set.contains(name) {
+                        InputState::Set
+                    } else if **value == Value::None {
+                        InputState::Unset
+                    } else {
+                        InputState::Defaulted
+                    };
+
+                    Some(InputInfo {
+                        name: name.clone(),
+                        kind: kind.clone(),
+                        state,
+                        required: needed.contains(name),
+                        annotations: self.annotations.get(name).cloned().unwrap_or_default(),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        inputs.sort_by(|a, b| a.name.cmp(&b.name));
+
+        inputs
+    }
+
+    // every variable something on the sheet currently reads, starting from the ones nothing else uses
+    // anything only used in a branch that isn't taken, or one waiting on an unset input, is left out
+    fn needed(&self) -> HashSet<String> {
+        let mut needed: HashSet<String> = HashSet::new();
+        let mut queue: VecDeque<String> = self
+            .exprs
+            .keys()
+            .filter(|name| self.dependents.get(*name).is_none_or(|e| e.is_empty()))
+            .cloned()
+            .collect();
+
+        while let Some(name) = queue.pop_front() {
+            if !needed.insert(name.clone()) {
+                continue;
+            }
+
+            // what the variable read when it was last evaluated, so nothing has to be evaluated again here
+            let deps = match self.live.get(&name) {
+                Some(deps) => deps.clone(),
+                None => self.deps.get(&name).cloned().unwrap_or_default(),
+            };
+
+            for dep in deps {
+                // fields of a record are reached through the record
+                let mut dep = dep.as_str();
+
+                while !self.exprs.contains_key(dep) {
+                    match dep.rsplit_once('.') {
+                        Some((prefix, _)) => dep = prefix,
+                        None => break,
+                    }
+                }
+
+                if !needed.contains(dep) {
+                    queue.push_back(dep.to_owned());
+                }
+            }
+        }
+
+        needed
+    }
+
+    // sets an input and reevaluates everything depending on it
+    // returns the names of all variables whose value changed, including the input itself
+    // errors about `value` itself, like it failing to parse or being the wrong type, have spans into `value` rather than the sheet
+    pub fn set_input(&mut self, name: &str, value: &str) -> Result<HashSet<String>, Vec<Error>> {
+        let len = value.chars().count();
+
+        let kind = match self.values.get(name) {
+            Some(Value::Input(_, kind, _)) => kind.clone(),
+            _ => {
+                return Err(Error::ReferenceError {
+                    name: name.to_owned(),
+                    span: 0..len,
+                }
+                .into())
+            }
+        };
 
         let (lexed, errs) = lexer::lexer().parse_recovery(value);
 
@@ -26,7 +126,7 @@ impl VarStore {
             return Err(errs.iter().map(|e| Error::SyntaxError(e.clone())).collect());
         }
 
-        let (parsed, errs) = parser::parse()
+        let (parsed, errs) = parser::parse_expr()
             .parse_recovery(Stream::from_iter(len..len + 1, lexed.unwrap().into_iter()));
 
         if errs.len() > 0 {
@@ -36,8 +136,334 @@ impl VarStore {
                 .collect());
         }
 
-        let parsed = &parsed.unwrap()[0];
+        let (evaluated, _) = eval(&parsed.unwrap(), HashMap::new())?;
+
+        if !kind.accepts(&evaluated.0) {
+            return Err(Error::TypeError {
+                expected: kind.into(),
+                got: evaluated,
+                context: TypeErrorCtx::Input,
+            }
+            .into());
+        }
+
+        if let Some(constraint) = self.constraints.get(name) {
+            check_constraint(name, &evaluated, constraint, self.values.clone())?;
+        }
+
+        let SpannedValue(evaluated, _) = evaluated;
+        let new = Value::Input(name.to_owned(), kind, Box::new(evaluated));
+        let mut changed = HashSet::new();
+
+        if self.values.get(name) != Some(&new) {
+            changed.insert(name.to_owned());
+        }
+
+        // the change is made on a copy, so a failure part of the way through leaves the store as it was
+        let mut next = self.clone();
+
+        next.values.insert(name.to_owned(), new);
+        next.cached.insert(name.to_owned(), true);
+        next.set.insert(name.to_owned());
+        next.inputs.retain(|(input, _)| input != name);
+
+        // everything downstream of the input is out of date now
+        let mut queue: VecDeque<String> = VecDeque::from([name.to_owned()]);
+        let mut checks: Vec<String> = Vec::new();
+
+        while let Some(next_name) = queue.pop_front() {
+            for dependent in next.dependents.get(&next_name).cloned().unwrap_or_default() {
+                // an input that's been set only depends on things through its constraint
+                if next.set.contains(&dependent) {
+                    if !checks.contains(&dependent) {
+                        checks.push(dependent);
+                    }
+
+                    continue;
+                }
+
+                if next.cached.get(&dependent) != Some(&false) {
+                    next.cached.insert(dependent.clone(), false);
+                    queue.push_back(dependent);
+                }
+            }
+        }
+
+        next.recompute(&mut changed)?;
+
+        // constraints on other inputs can only be checked once what they read has been recomputed
+        let mut errs: Vec<Error> = Vec::new();
+
+        for dependent in checks {
+            if let (Some(Value::Input(_, _, value)), Some(constraint)) = (
+                next.values.get(&dependent),
+                next.constraints.get(&dependent),
+            ) {
+                let value = SpannedValue(*value.clone(), constraint.1.clone());
+
+                if let Err(e) =
+                    check_constraint(&dependent, &value, constraint, next.values.clone())
+                {
+                    errs.extend(e);
+                }
+            }
+        }
+
+        if !errs.is_empty() {
+            return Err(errs);
+        }
+
+        *self = next;
+
+        Ok(changed)
+    }
+
+    // reevaluates every stale variable, making sure each one's dependencies are evaluated first
+    fn recompute(&mut self, changed: &mut HashSet<String>) -> Result<(), Vec<Error>> {
+        let mut stale: Vec<String> = self
+            .cached
+            .iter()
+            .filter(|(_, cached)| !**cached)
+            .map(|(name, _)| name.clone())
+            .filter(|name| !self.set.contains(name))
+            .collect();
+        stale.sort();
+
+        let mut errs: Vec<Error> = Vec::new();
+
+        loop {
+            let ready = stale.iter().position(|name| {
+                self.deps[name]
+                    .iter()
+                    .all(|dep| !stale.contains(dep) || dep == name)
+            });
+
+            let Some(idx) = ready else {
+                break;
+            };
+
+            let name = stale.remove(idx);
+
+            let evaluated = eval(&self.exprs[&name], self.values.clone()).and_then(|(value, _)| {
+                match self.constraints.get(&name) {
+                    Some(constraint) => {
+                        check_constraint(&name, &value, constraint, self.values.clone())?;
+                        Ok(value)
+                    }
+                    None => Ok(value),
+                }
+            });
+
+            match evaluated {
+                Ok(value) => {
+                    if self.values.get(&name) != Some(&value.0) {
+                        changed.insert(name.clone());
+                    }
+
+                    self.values.insert(name.clone(), value.0);
+
+                    let expr = &self.exprs[&name];
+                    if let Some(deps) =
+                        get_read_deps(expr, self.constraints.get(&name), &self.values)
+                    {
+                        self.live.insert(name.clone(), deps);
+                    }
+
+                    self.cached.insert(name, true);
+                }
+                Err(e) => errs.extend(e),
+            }
+        }
+
+        if !errs.is_empty() {
+            Err(errs)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use crate::{
+        error::Error,
+        interpreter::{interpret, InputState},
+        value::Value,
+    };
+
+    #[test]
+    fn set_input_recomputes_dependents() {
+        let mut store = interpret(
+            "input base;
+            doubled = base * 2;
+            total = doubled + 1;
+            other = 5;",
+        )
+        .unwrap();
+
+        assert_eq!(store.values.get("total").unwrap(), &Value::None);
+
+        let changed = store.set_input("base", "3").unwrap();
+
+        assert_eq!(
+            changed,
+            HashSet::from(["base".to_owned(), "doubled".to_owned(), "total".to_owned()])
+        );
+        assert_eq!(store.values.get("doubled").unwrap(), &Value::Int(6));
+        assert_eq!(store.values.get("total").unwrap(), &Value::Int(7));
+    }
+
+    #[test]
+    fn set_input_unchanged_value() {
+        let mut store = interpret("input base; doubled = base * 2;").unwrap();
+
+        store.set_input("base", "3").unwrap();
+        let changed = store.set_input("base", "3").unwrap();
+
+        assert!(changed.is_empty());
+    }
+
+    #[test]
+    fn set_input_wrong_type() {
+        let mut store = interpret("input name: String; shout = name * 3;").unwrap();
+
+        assert!(store.set_input("name", "12").is_err());
+        assert!(store.set_input("shout", "'nice'").is_err());
+        assert!(store.set_input("name", "'nice'").is_ok());
+    }
+
+    #[test]
+    fn set_input_single_expression() {
+        let mut store = interpret("input x; input name: String;").unwrap();
+
+        for value in ["y = 3;", "input z;", "1; 2", "1 2", ""] {
+            assert!(matches!(
+                &store.set_input("x", value).unwrap_err()[..],
+                [Error::ParsingError(_), ..]
+            ));
+        }
+        assert_eq!(store.get_inputs()[1].state, InputState::Unset);
+
+        // spans are in characters, like everywhere else
+        let errs = store.set_input("name", "'é' +").unwrap_err();
+        assert!(matches!(&errs[..], [Error::ParsingError(e)] if e.span() == (5..6)));
+    }
+
+    #[test]
+    fn set_input_constraint() {
+        let mut store = interpret("input level where @ >= 1; bonus = level * 2;").unwrap();
+
+        assert!(store.set_input("level", "0").is_err());
+        assert_eq!(store.values.get("bonus").unwrap(), &Value::None);
+        assert!(store.set_input("level", "3").is_ok());
+        assert_eq!(store.values.get("bonus").unwrap(), &Value::Int(6));
+    }
+
+    #[test]
+    fn set_input_failure_keeps_store() {
+        let mut store = interpret(
+            "input level;
+            input hp where @ <= cap;
+            cap = level * 10;
+            broken = 10 / (level - 2);",
+        )
+        .unwrap();
+
+        store.set_input("level", "3").unwrap();
+        store.set_input("hp", "25").unwrap();
+        let before = store.values.clone();
+
+        // the new cap is below hp, which is only known once cap is recomputed
+        assert!(store.set_input("level", "1").is_err());
+        assert_eq!(store.values, before);
+        assert_eq!(store.values.get("cap").unwrap(), &Value::Int(30));
+
+        // dividing by zero fails part of the way through recomputing
+        assert!(store.set_input("level", "2").is_err());
+        assert_eq!(store.values, before);
+        assert_eq!(
+            store.get_inputs()[0].state,
+            crate::interpreter::InputState::Set
+        );
+
+        assert!(store.set_input("level", "4").is_ok());
+        assert_eq!(store.values.get("cap").unwrap(), &Value::Int(40));
+    }
+
+    #[test]
+    fn inputs_required() {
+        let mut store = interpret(
+            "input has_shield: Bool;
+            input shield_ac: Int;
+            input base: Int = 10;
+            shield = shield_ac + 1;
+            ac = if has_shield { base + shield } else { base };
+            blocks = has_shield and shield_ac > 0;",
+        )
+        .unwrap();
+
+        let required = |store: &crate::interpreter::VarStore| -> Vec<String> {
+            store
+                .get_inputs()
+                .into_iter()
+                .filter(|input| input.required)
+                .map(|input| input.name)
+                .collect()
+        };
+
+        // nothing knows whether there's a shield yet
+        assert_eq!(required(&store), vec!["base", "has_shield"]);
+
+        store.set_input("has_shield", "false").unwrap();
+        assert_eq!(required(&store), vec!["base", "has_shield"]);
+        assert_eq!(store.values.get("ac").unwrap(), &Value::Int(10));
+        assert_eq!(store.values.get("blocks").unwrap(), &Value::Bool(false));
+
+        store.set_input("has_shield", "true").unwrap();
+        assert_eq!(required(&store), vec!["base", "has_shield", "shield_ac"]);
+    }
+
+    #[test]
+    fn set_input_typed() {
+        let mut store =
+            interpret("input level: Int; input bonus: Num; input parts: [Int];").unwrap();
+
+        assert!(store.set_input("level", "2.5").is_err());
+        assert!(store.set_input("level", "3").is_ok());
+        assert!(store.set_input("bonus", "3").is_ok());
+        assert!(store.set_input("parts", "[1, 'two']").is_err());
+        assert!(store.set_input("parts", "[1, 2]").is_ok());
+    }
+
+    #[test]
+    fn set_input_overrides_default() {
+        let mut store = interpret("input base = 10; input other; bonus = base + 2;").unwrap();
+
+        assert_eq!(store.values.get("bonus").unwrap(), &Value::Int(12));
+        let inputs = store.get_inputs();
+
+        assert_eq!(inputs[0].name, "base");
+        assert_eq!(inputs[0].state, InputState::Defaulted);
+        assert_eq!(inputs[1].name, "other");
+        assert_eq!(inputs[1].state, InputState::Unset);
+
+        store.set_input("base", "15").unwrap();
+
+        assert_eq!(store.values.get("bonus").unwrap(), &Value::Int(17));
+        assert_eq!(store.get_inputs()[0].state, InputState::Set);
+    }
+
+    #[test]
+    fn default_follows_dependencies() {
+        let mut store = interpret("input level; input hp = level * 4; total = hp + 1;").unwrap();
+
+        store.set_input("level", "3").unwrap();
+        assert_eq!(store.values.get("total").unwrap(), &Value::Int(13));
 
-        Ok(Self::new())
+        store.set_input("hp", "20").unwrap();
+        store.set_input("level", "5").unwrap();
+        assert_eq!(store.values.get("total").unwrap(), &Value::Int(21));
     }
 }
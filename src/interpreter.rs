@@ -1,22 +1,26 @@
 use std::{
-    collections::{HashMap, VecDeque},
-    hash::Hash,
+    collections::{HashMap, HashSet, VecDeque},
+    io,
 };
 
 use chumsky::{Parser, Stream};
+use serde::Serialize;
 
 use crate::{
-    ast::{Expr, Span, Spanned},
+    ast::{Annotations, Expr, FormatPart, InfixOp, Literal, Modifiers, Span, Spanned},
+    builtins,
     error::Error,
-    eval::eval,
-    lexer, parser,
+    eval::{check_constraint, eval, is_pending},
+    imports, lexer, parser,
+    sources::SourceMap,
+    templates,
     value::{Value, ValueType},
 };
 
 #[derive(Clone, Debug)]
 pub struct SpannedIdent {
-    name: String,
-    span: Span,
+    pub(crate) name: String,
+    pub(crate) span: Span,
 }
 
 impl PartialEq<SpannedIdent> for SpannedIdent {
@@ -45,10 +49,58 @@ pub struct VarStore {
     pub(crate) source: HashMap<String, String>, // generated source for each variable, so it can be serialized easier
     pub(crate) cached: HashMap<String, bool>,   // whether the cached value for a variable is valid
     pub(crate) intermediate: HashMap<String, HashMap<String, Expr>>,
+    pub(crate) exprs: HashMap<String, Spanned>, // expression for each variable, for reevaluating it later
+    pub(crate) constraints: HashMap<String, Spanned>, // `where` clause of each variable that has one
+    pub(crate) set: HashSet<String>, // inputs that have been given a value, rather than using their default
+    pub(crate) live: HashMap<String, Vec<String>>, // what each variable read the last time it was evaluated, skipping branches that weren't taken
+    pub(crate) modifiers: HashMap<String, Modifiers>, // `pub` and `out` on each declaration
+    pub(crate) annotations: HashMap<String, HashMap<String, Value>>, // `#[..]` metadata on each declaration and block
+}
+
+// everything a host needs to build a form field for an input
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct InputInfo {
+    pub name: String,
+    pub kind: ValueType,
+    pub state: InputState,
+    pub required: bool, // whether anything evaluated right now reads it, so inputs in branches that aren't taken can be hidden
+    pub annotations: HashMap<String, Value>,
+}
+
+// where the value of an input is coming from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum InputState {
+    Unset,     // no value, so nothing depending on it can be evaluated
+    Defaulted, // using the default from its declaration
+    Set,       // given a value with `VarStore::set_input`
 }
 
 pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
-    let len = input.len();
+    let parsed = parse_source(input, 0)?;
+    imports::reject(&parsed)?;
+
+    build(&parsed)
+}
+
+// a sheet read from `path`, along with every file it uses
+pub fn interpret_file(path: &str, sources: &mut SourceMap) -> Result<VarStore, Vec<Error>> {
+    interpret_with(path, sources, &mut |path| std::fs::read_to_string(path))
+}
+
+// like `interpret_file`, but files are read with `load`, for hosts that don't keep sheets on disk
+pub fn interpret_with(
+    path: &str,
+    sources: &mut SourceMap,
+    load: &mut dyn FnMut(&str) -> io::Result<String>,
+) -> Result<VarStore, Vec<Error>> {
+    let parsed = imports::load(path, sources, load)?;
+
+    build(&parsed)
+}
+
+// every span is moved by `offset`, so it can be told apart from spans in other files
+pub(crate) fn parse_source(input: &str, offset: usize) -> Result<Vec<Spanned>, Vec<Error>> {
+    let len = input.chars().count();
 
     let (lexed, errs) = lexer::lexer().parse_recovery(input);
 
@@ -56,8 +108,13 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
         return Err(errs.iter().map(|e| Error::SyntaxError(e.clone())).collect());
     }
 
-    let (parsed, errs) =
-        parser::parse().parse_recovery(Stream::from_iter(len..len + 1, lexed.unwrap().into_iter()));
+    let lexed = lexed
+        .unwrap()
+        .into_iter()
+        .map(|(token, span)| (token, span.start + offset..span.end + offset));
+    let eoi = offset + len;
+
+    let (parsed, errs) = parser::parse().parse_recovery(Stream::from_iter(eoi..eoi + 1, lexed));
 
     if errs.len() > 0 {
         return Err(errs
@@ -66,7 +123,15 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
             .collect());
     }
 
-    let parsed = parsed.unwrap();
+    Ok(parsed.unwrap())
+}
+
+fn build(parsed: &[Spanned]) -> Result<VarStore, Vec<Error>> {
+    let parsed = templates::expand(parsed)?;
+    let mut annotations: HashMap<String, HashMap<String, Value>> = HashMap::new();
+    get_annotations(&parsed, "", &mut annotations);
+
+    let parsed = flatten(&parsed);
 
     let mut spans: HashMap<String, Span> = HashMap::new();
     let mut errs: Vec<Error> = Vec::new();
@@ -78,8 +143,17 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
     // check dependencies of variables
     for expr in parsed.iter() {
         match expr {
-            Spanned(Expr::Assign { names, value }, span) => {
-                let value_deps = get_deps(value);
+            Spanned(
+                Expr::Assign {
+                    names,
+                    value,
+                    constraint,
+                    ..
+                },
+                span,
+            ) => {
+                let mut value_deps = get_deps(value);
+                value_deps.extend(get_constraint_deps(constraint.as_deref()));
 
                 for name in names {
                     if let Some(old_span) = spans.get(name) {
@@ -107,7 +181,16 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
                     }
                 }
             }
-            Spanned(Expr::Input(name, _), span) => {
+            Spanned(
+                Expr::Input {
+                    name,
+                    kind: _,
+                    default,
+                    constraint,
+                    ..
+                },
+                span,
+            ) => {
                 if let Some(old_span) = spans.get(name) {
                     let err = Error::ReassignError {
                         name: name.to_string(),
@@ -116,10 +199,20 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
                     };
                     errs.push(err);
                 } else {
+                    let mut input_deps = get_constraint_deps(constraint.as_deref());
+
+                    if let Some(default) = default {
+                        input_deps.extend(get_deps(default));
+                    }
+
                     spans.insert(name.clone(), span.clone());
-                    deps.insert(name.to_owned(), (Vec::new(), span.clone()));
-                    out_deps.insert(name.to_owned(), Vec::new());
-                    cached.insert(name.to_owned(), false);
+                    deps.insert(name.to_owned(), (input_deps.clone(), span.clone()));
+                    out_deps.insert(name.to_owned(), input_deps.clone());
+                    cached.insert(name.to_owned(), true);
+
+                    for dep in input_deps {
+                        dependents.entry(dep).or_default().push(name.to_owned());
+                    }
                 }
             }
             _ => {}
@@ -172,17 +265,46 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
     }
 
     let mut exprs: HashMap<String, Spanned> = HashMap::new();
+    let mut constraints: HashMap<String, Spanned> = HashMap::new();
+    let mut modifiers: HashMap<String, Modifiers> = HashMap::new();
 
     // gather the variable assignments without evaluating them
     for expr in parsed.iter() {
         match expr {
-            Spanned(Expr::Assign { names, value }, _) => {
+            Spanned(
+                Expr::Assign {
+                    names,
+                    value,
+                    constraint,
+                    modifiers: found,
+                    ..
+                },
+                _,
+            ) => {
                 for name in names {
                     exprs.insert(name.clone(), *value.clone());
+                    modifiers.insert(name.clone(), *found);
+
+                    if let Some(constraint) = constraint {
+                        constraints.insert(name.clone(), *constraint.clone());
+                    }
                 }
             }
-            Spanned(Expr::Input(name, _), _) => {
+            Spanned(
+                Expr::Input {
+                    name,
+                    constraint,
+                    modifiers: found,
+                    ..
+                },
+                _,
+            ) => {
                 exprs.insert(name.clone(), expr.clone());
+                modifiers.insert(name.clone(), *found);
+
+                if let Some(constraint) = constraint {
+                    constraints.insert(name.clone(), *constraint.clone());
+                }
             }
             _ => {}
         }
@@ -191,6 +313,7 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
     let mut vars: HashMap<String, Value> = HashMap::new();
     let mut source: HashMap<String, String> = HashMap::new();
     let mut inputs: Vec<(String, ValueType)> = Vec::new();
+    let mut live: HashMap<String, Vec<String>> = HashMap::new();
 
     // finally, evaluate the variables
     for SpannedIdent { name, span: _ } in order {
@@ -200,10 +323,22 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
         let expr_source = exprs.get(&name).unwrap().clone().into();
         source.insert(name.clone(), expr_source);
 
+        let evaluated = match (evaluated, constraints.get(&name)) {
+            (Ok((value, inputs_out)), Some(constraint)) => {
+                check_constraint(&name, &value, constraint, vars.clone())
+                    .map(|_| (value, inputs_out))
+            }
+            (evaluated, _) => evaluated,
+        };
+
         match evaluated {
             Ok((value, inputs_out)) => {
                 inputs.extend(inputs_out);
-                vars.insert(name, value.0);
+                vars.insert(name.clone(), value.0);
+
+                if let Some(deps) = get_read_deps(&exprs[&name], constraints.get(&name), &vars) {
+                    live.insert(name, deps);
+                }
             }
             Err(e) => {
                 errs.extend(e);
@@ -222,32 +357,186 @@ pub fn interpret(input: &str) -> Result<VarStore, Vec<Error>> {
             source,
             cached,
             intermediate: HashMap::new(),
+            exprs,
+            constraints,
+            set: HashSet::new(),
+            live,
+            modifiers,
+            annotations,
         })
     }
 }
 
-fn get_deps(expr: &Spanned) -> Vec<String> {
+pub(crate) fn get_deps(expr: &Spanned) -> Vec<String> {
+    collect_deps(expr, None)
+}
+
+// only what evaluating the expression with these values actually reads, skipping branches that aren't taken
+fn get_live_deps(expr: &Spanned, vars: &HashMap<String, Value>) -> Vec<String> {
+    collect_deps(expr, Some(vars))
+}
+
+// what a variable that was just evaluated read, including its `where` clause
+// `None` for inputs, which only read anything through their default and constraint, so all of `deps` counts
+pub(crate) fn get_read_deps(
+    expr: &Spanned,
+    constraint: Option<&Spanned>,
+    vars: &HashMap<String, Value>,
+) -> Option<Vec<String>> {
+    match expr {
+        Spanned(Expr::Input { .. }, _) => None,
+        _ => {
+            let mut deps = get_live_deps(expr, vars);
+            deps.extend(get_constraint_deps(constraint));
+
+            Some(deps)
+        }
+    }
+}
+
+// without `vars` every branch is followed
+// anything with names of its own is always followed fully, since its conditions can't be evaluated out here
+fn collect_deps(expr: &Spanned, vars: Option<&HashMap<String, Value>>) -> Vec<String> {
     let mut deps: Vec<String> = Vec::new();
 
     match expr {
         Spanned(Expr::Ident(name), _) => {
             deps.push(name.clone());
         }
+        Spanned(Expr::InfixOp(lhs, op @ (InfixOp::And | InfixOp::Or), rhs), _) => {
+            deps.extend(collect_deps(lhs, vars));
+
+            // the right side is skipped when the left side decides it, or can't be evaluated yet
+            match vars.map(|vars| decided(lhs, vars)) {
+                Some(Some(Value::Bool(lhs))) if lhs == (*op == InfixOp::Or) => {}
+                Some(Some(ref lhs)) if is_pending(lhs) => {}
+                _ => deps.extend(collect_deps(rhs, vars)),
+            }
+        }
         Spanned(Expr::InfixOp(lhs, _, rhs), _) => {
-            deps.extend(get_deps(lhs));
-            deps.extend(get_deps(rhs));
+            deps.extend(collect_deps(lhs, vars));
+            deps.extend(collect_deps(rhs, vars));
+        }
+        Spanned(Expr::Compare { first, rest }, _) => {
+            deps.extend(collect_deps(first, vars));
+
+            for (_, rhs) in rest {
+                deps.extend(collect_deps(rhs, vars));
+            }
         }
         Spanned(Expr::Index(lhs, idx), _) => {
-            deps.extend(get_deps(lhs));
-            deps.extend(get_deps(idx));
+            deps.extend(collect_deps(lhs, vars));
+            deps.extend(collect_deps(idx, vars));
+        }
+        Spanned(Expr::Not(rhs), _) | Spanned(Expr::Neg(rhs), _) => {
+            deps.extend(collect_deps(rhs, vars));
         }
-        Spanned(Expr::Not(rhs), _) => {
-            deps.extend(get_deps(rhs));
+        Spanned(Expr::Literal(Literal::Array(items)), _) => {
+            for item in items {
+                deps.extend(collect_deps(item, vars));
+            }
+        }
+        Spanned(Expr::Literal(Literal::Record(fields)), _) => {
+            for (_, field) in fields {
+                deps.extend(collect_deps(field, vars));
+            }
         }
         Spanned(Expr::Literal(_), _) => {}
-        Spanned(Expr::Assign { names: _, value: _ }, _) => {
-            unreachable!("Assigns can never be in the value of an assignment")
+        Spanned(Expr::Assign { .. }, _) | Spanned(Expr::Input { .. }, _) => {
+            unreachable!("Declarations can never be in the value of an assignment")
+        }
+        Spanned(Expr::Access(lhs, _), _) => match expr.path() {
+            Some(name) => deps.push(name),
+            None => deps.extend(collect_deps(lhs, vars)),
+        },
+        Spanned(Expr::Call(callee, args), _) => {
+            match callee.path() {
+                Some(name) if builtins::get(&name).is_some() => {}
+                _ => deps.extend(collect_deps(callee, vars)),
+            }
+
+            for arg in args {
+                deps.extend(collect_deps(arg, vars));
+            }
+        }
+        Spanned(Expr::Block { .. }, _) => {
+            unreachable!("Blocks are flattened before looking for dependencies")
+        }
+        Spanned(Expr::Template(_), _) | Spanned(Expr::Member { .. }, _) => {
+            unreachable!("Templates are expanded before looking for dependencies")
+        }
+        Spanned(Expr::Use { .. }, _) => {
+            unreachable!("Imports are loaded before looking for dependencies")
+        }
+        // parameters and the function's own name aren't sheet variables
+        Spanned(Expr::Lambda { name, params, body }, _) => {
+            let local = |dep: &String| {
+                name.as_ref() == Some(dep)
+                    || params
+                        .iter()
+                        .any(|param| dep == param || dep.starts_with(&(param.clone() + ".")))
+            };
+
+            deps.extend(
+                collect_deps(body, None)
+                    .into_iter()
+                    .filter(|dep| !local(dep)),
+            );
+        }
+        Spanned(Expr::Let { name, value, body }, _) => {
+            deps.extend(collect_deps(value, vars));
+            deps.extend(
+                collect_deps(body, None)
+                    .into_iter()
+                    .filter(|dep| dep != name && !dep.starts_with(&(name.clone() + "."))),
+            );
+        }
+        Spanned(Expr::Match { value, arms }, _) => {
+            deps.extend(collect_deps(value, vars));
+
+            for (patterns, body) in arms {
+                for pattern in patterns {
+                    deps.extend(
+                        collect_deps(pattern, vars)
+                            .into_iter()
+                            .filter(|dep| dep != "_"),
+                    );
+                }
+
+                deps.extend(collect_deps(body, vars));
+            }
+        }
+        Spanned(Expr::Format(parts), _) => {
+            for part in parts {
+                if let FormatPart::Value(value, _) = part {
+                    deps.extend(collect_deps(value, vars));
+                }
+            }
         }
+        Spanned(
+            Expr::Comprehension {
+                item,
+                var,
+                source,
+                condition,
+            },
+            _,
+        ) => {
+            let mut local = collect_deps(item, None);
+
+            if let Some(condition) = condition {
+                local.extend(collect_deps(condition, None));
+            }
+
+            deps.extend(collect_deps(source, vars));
+            deps.extend(
+                local
+                    .into_iter()
+                    .filter(|dep| dep != var && !dep.starts_with(&(var.clone() + "."))),
+            );
+        }
+        // only valid inside templates, which is reported when it's evaluated
+        Spanned(Expr::Macro(_, _), _) => {}
         Spanned(Expr::Error, _) => {}
         Spanned(
             Expr::Conditional {
@@ -257,41 +546,439 @@ fn get_deps(expr: &Spanned) -> Vec<String> {
             },
             _,
         ) => {
-            deps.extend(get_deps(condition));
-            deps.extend(get_deps(inner));
-            deps.extend(get_deps(other));
+            deps.extend(collect_deps(condition, vars));
+
+            match vars.map(|vars| decided(condition, vars)) {
+                Some(Some(Value::Bool(true))) => deps.extend(collect_deps(inner, vars)),
+                Some(Some(Value::Bool(false))) => deps.extend(collect_deps(other, vars)),
+                // until the condition's inputs are given, only what both branches read is needed for sure
+                Some(Some(ref condition)) if is_pending(condition) => {
+                    let other = collect_deps(other, vars);
+
+                    deps.extend(
+                        collect_deps(inner, vars)
+                            .into_iter()
+                            .filter(|dep| other.contains(dep)),
+                    );
+                }
+                _ => {
+                    deps.extend(collect_deps(inner, vars));
+                    deps.extend(collect_deps(other, vars));
+                }
+            }
         }
-        _ => todo!(),
     }
 
     deps
 }
 
-pub fn get_inputs(input: &str) -> Result<Vec<(String, ValueType)>, Vec<Error>> {
-    let len = input.len();
+// the value of a condition, or `None` if it fails and so doesn't tell which branch is taken
+fn decided(condition: &Spanned, vars: &HashMap<String, Value>) -> Option<Value> {
+    eval(condition, vars.clone()).ok().map(|(value, _)| value.0)
+}
 
-    let (lexed, errs) = lexer::lexer().parse_recovery(input);
+// what a `where` clause depends on, apart from the value it's checking
+pub(crate) fn get_constraint_deps(constraint: Option<&Spanned>) -> Vec<String> {
+    match constraint {
+        Some(constraint) => get_deps(constraint)
+            .into_iter()
+            .filter(|dep| dep != "@" && !dep.starts_with("@."))
+            .collect(),
+        None => Vec::new(),
+    }
+}
 
-    if errs.len() > 0 {
-        return Err(errs.iter().map(|e| Error::SyntaxError(e.clone())).collect());
+// lifts everything declared inside blocks to the top level, qualifying their names with the blocks they're in
+fn flatten(items: &[Spanned]) -> Vec<Spanned> {
+    let mut declared: HashSet<String> = HashSet::new();
+    let mut out: Vec<Spanned> = Vec::new();
+
+    get_declared(items, "", &mut declared);
+    flatten_into(items, "", &declared, &mut out);
+
+    out
+}
+
+fn qualify(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        prefix.to_owned() + "." + name
     }
+}
 
-    let (parsed, errs) =
-        parser::parse().parse_recovery(Stream::from_iter(len..len + 1, lexed.unwrap().into_iter()));
+fn get_declared(items: &[Spanned], prefix: &str, declared: &mut HashSet<String>) {
+    for item in items {
+        match item {
+            Spanned(Expr::Assign { names, .. }, _) => {
+                declared.extend(names.iter().map(|name| qualify(prefix, name)));
+            }
+            Spanned(Expr::Input { name, .. }, _) => {
+                declared.insert(qualify(prefix, name));
+            }
+            Spanned(Expr::Block { name, items, .. }, _) => {
+                get_declared(items, &qualify(prefix, name), declared);
+            }
+            _ => {}
+        }
+    }
+}
 
-    if errs.len() > 0 {
-        return Err(errs
+// collects the `#[..]` metadata of every declaration and block under its qualified name
+fn get_annotations(
+    items: &[Spanned],
+    prefix: &str,
+    out: &mut HashMap<String, HashMap<String, Value>>,
+) {
+    let collect = |annotations: &Annotations| -> HashMap<String, Value> {
+        annotations
             .iter()
-            .map(|e| Error::ParsingError(e.clone()))
-            .collect());
+            .map(|(key, value)| (key.clone(), Value::from(value.clone())))
+            .collect()
+    };
+
+    for item in items {
+        match item {
+            Spanned(
+                Expr::Assign {
+                    names, annotations, ..
+                },
+                _,
+            ) => {
+                for name in names {
+                    out.insert(qualify(prefix, name), collect(annotations));
+                }
+            }
+            Spanned(
+                Expr::Input {
+                    name, annotations, ..
+                },
+                _,
+            ) => {
+                out.insert(qualify(prefix, name), collect(annotations));
+            }
+            Spanned(
+                Expr::Block {
+                    name,
+                    items,
+                    annotations,
+                },
+                _,
+            ) => {
+                let name = qualify(prefix, name);
+
+                out.insert(name.clone(), collect(annotations));
+                get_annotations(items, &name, out);
+            }
+            _ => {}
+        }
     }
+}
 
-    let parsed = parsed.unwrap();
-    let mut inputs: Vec<(String, ValueType)> = Vec::new();
+fn flatten_into(
+    items: &[Spanned],
+    prefix: &str,
+    declared: &HashSet<String>,
+    out: &mut Vec<Spanned>,
+) {
+    for item in items {
+        match item {
+            Spanned(
+                Expr::Assign {
+                    names,
+                    value,
+                    constraint,
+                    modifiers,
+                    annotations,
+                },
+                span,
+            ) => {
+                let assign = Expr::Assign {
+                    names: names.iter().map(|name| qualify(prefix, name)).collect(),
+                    value: Box::new(resolve(value, prefix, declared)),
+                    constraint: resolve_opt(constraint, prefix, declared),
+                    modifiers: *modifiers,
+                    annotations: annotations.clone(),
+                };
+
+                out.push(Spanned(assign, span.clone()));
+            }
+            Spanned(
+                Expr::Input {
+                    name,
+                    kind,
+                    default,
+                    constraint,
+                    modifiers,
+                    annotations,
+                },
+                span,
+            ) => {
+                let input = Expr::Input {
+                    name: qualify(prefix, name),
+                    kind: kind.clone(),
+                    default: resolve_opt(default, prefix, declared),
+                    constraint: resolve_opt(constraint, prefix, declared),
+                    modifiers: *modifiers,
+                    annotations: annotations.clone(),
+                };
+
+                out.push(Spanned(input, span.clone()));
+            }
+            Spanned(Expr::Block { name, items, .. }, _) => {
+                flatten_into(items, &qualify(prefix, name), declared, out);
+            }
+            _ => out.push(resolve(item, prefix, declared)),
+        }
+    }
+}
+
+// points references at the fully qualified name they mean from inside the block at `prefix`
+// the innermost block declaring a name wins, falling back outwards to the top level
+fn resolve(expr: &Spanned, prefix: &str, declared: &HashSet<String>) -> Spanned {
+    if let Some(path) = expr.path() {
+        let mut scope = prefix;
+
+        loop {
+            let name = qualify(scope, &path);
+
+            if declared.contains(&name) {
+                return Spanned(Expr::Ident(name), expr.1.clone());
+            }
+
+            if scope.is_empty() {
+                break;
+            }
+
+            scope = match scope.rfind('.') {
+                Some(idx) => &scope[..idx],
+                None => "",
+            };
+        }
+    }
+
+    let resolved = match &expr.0 {
+        Expr::Literal(Literal::Array(items)) => Expr::Literal(Literal::Array(
+            items
+                .iter()
+                .map(|item| resolve(item, prefix, declared))
+                .collect(),
+        )),
+        Expr::Not(rhs) => Expr::Not(Box::new(resolve(rhs, prefix, declared))),
+        Expr::Neg(rhs) => Expr::Neg(Box::new(resolve(rhs, prefix, declared))),
+        Expr::InfixOp(lhs, op, rhs) => Expr::InfixOp(
+            Box::new(resolve(lhs, prefix, declared)),
+            *op,
+            Box::new(resolve(rhs, prefix, declared)),
+        ),
+        Expr::Compare { first, rest } => Expr::Compare {
+            first: Box::new(resolve(first, prefix, declared)),
+            rest: rest
+                .iter()
+                .map(|(op, rhs)| (*op, resolve(rhs, prefix, declared)))
+                .collect(),
+        },
+        Expr::Index(lhs, idx) => Expr::Index(
+            Box::new(resolve(lhs, prefix, declared)),
+            Box::new(resolve(idx, prefix, declared)),
+        ),
+        Expr::Conditional {
+            condition,
+            inner,
+            other,
+        } => Expr::Conditional {
+            condition: Box::new(resolve(condition, prefix, declared)),
+            inner: Box::new(resolve(inner, prefix, declared)),
+            other: Box::new(resolve(other, prefix, declared)),
+        },
+        Expr::Literal(Literal::Record(fields)) => Expr::Literal(Literal::Record(
+            fields
+                .iter()
+                .map(|(name, field)| (name.clone(), resolve(field, prefix, declared)))
+                .collect(),
+        )),
+        Expr::Access(lhs, member) => {
+            let lhs = resolve(lhs, prefix, declared);
+
+            match (lhs.path(), &member.0) {
+                // still not a declared name, so it's reported as missing later
+                (Some(path), _) if !declared.contains(&path) => {
+                    Expr::Access(Box::new(lhs), member.clone())
+                }
+                // a field of a record
+                (_, Expr::Ident(name)) => Expr::Index(
+                    Box::new(lhs),
+                    Box::new(Spanned(
+                        Expr::Literal(Literal::String(name.clone())),
+                        member.1.clone(),
+                    )),
+                ),
+                _ => Expr::Access(Box::new(lhs), member.clone()),
+            }
+        }
+        Expr::Call(callee, args) => Expr::Call(
+            Box::new(resolve(callee, prefix, declared)),
+            args.iter()
+                .map(|arg| resolve(arg, prefix, declared))
+                .collect(),
+        ),
+        Expr::Lambda { name, params, body } => {
+            let visible = shadow(declared, prefix, params);
+
+            // a named function refers to itself by its qualified name
+            let name = name.as_ref().map(|name| {
+                match resolve(
+                    &Spanned(Expr::Ident(name.clone()), expr.1.clone()),
+                    prefix,
+                    declared,
+                )
+                .0
+                {
+                    Expr::Ident(qualified) => qualified,
+                    _ => name.clone(),
+                }
+            });
+
+            Expr::Lambda {
+                name,
+                params: params.clone(),
+                body: Box::new(resolve(body, prefix, &visible)),
+            }
+        }
+        Expr::Let { name, value, body } => Expr::Let {
+            name: name.clone(),
+            value: Box::new(resolve(value, prefix, declared)),
+            body: Box::new(resolve(
+                body,
+                prefix,
+                &shadow(declared, prefix, std::slice::from_ref(name)),
+            )),
+        },
+        Expr::Match { value, arms } => Expr::Match {
+            value: Box::new(resolve(value, prefix, declared)),
+            arms: arms
+                .iter()
+                .map(|(patterns, body)| {
+                    let patterns = patterns
+                        .iter()
+                        .map(|pattern| resolve(pattern, prefix, declared))
+                        .collect();
+
+                    (patterns, resolve(body, prefix, declared))
+                })
+                .collect(),
+        },
+        Expr::Format(parts) => Expr::Format(
+            parts
+                .iter()
+                .map(|part| match part {
+                    FormatPart::Value(value, spec) => {
+                        FormatPart::Value(resolve(value, prefix, declared), spec.clone())
+                    }
+                    text => text.clone(),
+                })
+                .collect(),
+        ),
+        Expr::Comprehension {
+            item,
+            var,
+            source,
+            condition,
+        } => {
+            let visible = shadow(declared, prefix, std::slice::from_ref(var));
+
+            Expr::Comprehension {
+                item: Box::new(resolve(item, prefix, &visible)),
+                var: var.clone(),
+                source: Box::new(resolve(source, prefix, declared)),
+                condition: resolve_opt(condition, prefix, &visible),
+            }
+        }
+        other => other.clone(),
+    };
+
+    Spanned(resolved, expr.1.clone())
+}
+
+// the declared names still visible after `locals` are bound inside the block at `prefix`
+// a local hides every variable it could otherwise resolve to, and anything inside it
+fn shadow(declared: &HashSet<String>, prefix: &str, locals: &[String]) -> HashSet<String> {
+    let shadowed = |declared_name: &String| {
+        locals.iter().any(|local| {
+            let mut scope = prefix;
+
+            loop {
+                let name = qualify(scope, local);
+
+                if *declared_name == name || declared_name.starts_with(&(name + ".")) {
+                    return true;
+                }
+
+                if scope.is_empty() {
+                    return false;
+                }
+
+                scope = match scope.rfind('.') {
+                    Some(idx) => &scope[..idx],
+                    None => "",
+                };
+            }
+        })
+    };
+
+    declared
+        .iter()
+        .filter(|name| !shadowed(name))
+        .cloned()
+        .collect()
+}
+
+fn resolve_opt(
+    expr: &Option<Box<Spanned>>,
+    prefix: &str,
+    declared: &HashSet<String>,
+) -> Option<Box<Spanned>> {
+    expr.as_ref()
+        .map(|expr| Box::new(resolve(expr, prefix, declared)))
+}
+
+// read the same way as `interpret`, so a sheet with `use` is an error here too
+pub fn get_inputs(input: &str) -> Result<Vec<InputInfo>, Vec<Error>> {
+    let parsed = parse_source(input, 0)?;
+    imports::reject(&parsed)?;
+
+    let parsed = templates::expand(&parsed)?;
+    let mut annotations: HashMap<String, HashMap<String, Value>> = HashMap::new();
+    get_annotations(&parsed, "", &mut annotations);
+
+    let parsed = flatten(&parsed);
+    let mut inputs: Vec<InputInfo> = Vec::new();
 
     for item in parsed {
         match item {
-            Spanned(Expr::Input(name, kind), _) => inputs.push((name, kind)),
+            Spanned(
+                Expr::Input {
+                    name,
+                    kind,
+                    default,
+                    ..
+                },
+                _,
+            ) => {
+                let state = match default {
+                    Some(_) => InputState::Defaulted,
+                    None => InputState::Unset,
+                };
+
+                inputs.push(InputInfo {
+                    annotations: annotations.remove(&name).unwrap_or_default(),
+                    name,
+                    kind,
+                    state,
+                    // nothing's been evaluated yet, so every input might be needed
+                    required: true,
+                })
+            }
             _ => {}
         }
     }
@@ -337,17 +1024,355 @@ fn gather_deps_errors(
 
 #[cfg(test)]
 mod tests {
-    use crate::value::Value;
+    use std::{collections::HashMap, io};
+
+    use crate::{
+        error::Error,
+        sources::SourceMap,
+        value::{Value, ValueType},
+    };
 
-    use super::interpret;
+    use super::{get_inputs, interpret, interpret_with, InputState, VarStore};
+
+    fn interpret_files(path: &str, files: &[(&str, &str)]) -> Result<VarStore, Vec<Error>> {
+        let files: HashMap<&str, &str> = files.iter().copied().collect();
+        let mut load = |path: &str| match files.get(path) {
+            Some(source) => Ok(source.to_string()),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "file not found")),
+        };
+
+        interpret_with(path, &mut SourceMap::default(), &mut load)
+    }
 
     #[test]
     fn interpret_assign_chain() {
         let interpreted = interpret("these = are = all = 12;").unwrap().values;
-        let value = Value::Num(12.0);
+        let value = Value::Int(12);
 
         assert_eq!(interpreted.get("these").unwrap(), &value);
         assert_eq!(interpreted.get("are").unwrap(), &value);
         assert_eq!(interpreted.get("all").unwrap(), &value)
     }
+
+    #[test]
+    fn interpret_block() {
+        let interpreted = interpret(
+            "scores {
+                str = 16;
+                dex = str - 2;
+            }
+
+            modifiers {
+                str = (scores.str - 10) div 2;
+            }",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("scores.dex").unwrap(), &Value::Int(14));
+        assert_eq!(interpreted.get("modifiers.str").unwrap(), &Value::Int(3));
+    }
+
+    #[test]
+    fn interpret_nested_block() {
+        let interpreted = interpret(
+            "level = 3;
+
+            hp {
+                base = 10;
+
+                bonus {
+                    per_level = base div 2;
+                    total = per_level * level;
+                }
+            }
+
+            total = hp.bonus.total + hp.base;",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("hp.bonus.total").unwrap(), &Value::Int(15));
+        assert_eq!(interpreted.get("total").unwrap(), &Value::Int(25));
+    }
+
+    #[test]
+    fn interpret_block_missing_member() {
+        let interpreted = interpret("scores { str = 16; } nice = scores.dex;");
+
+        match interpreted {
+            Err(errs) => match &errs[0] {
+                Error::ReferenceError { name, span: _ } => assert_eq!(name, "scores.dex"),
+                e => panic!("expected a reference error, got {:?}", e),
+            },
+            Ok(_) => panic!("expected a reference error"),
+        }
+    }
+
+    #[test]
+    fn interpret_constraint() {
+        let interpreted = interpret("cap = 20; nice = 12 where @ <= cap;");
+        assert!(interpreted.is_ok());
+
+        let interpreted = interpret("cap = 10; nice = 12 where @ <= cap;");
+
+        match interpreted {
+            Err(errs) => match &errs[0] {
+                Error::ConstraintError { name, .. } => assert_eq!(name, "nice"),
+                e => panic!("expected a constraint error, got {:?}", e),
+            },
+            Ok(_) => panic!("expected a constraint error"),
+        }
+    }
+
+    #[test]
+    fn interpret_input_default() {
+        let interpreted = interpret("input str = 14; mod = floor((str - 10) / 2);")
+            .unwrap()
+            .values;
+
+        assert_eq!(interpreted.get("mod").unwrap(), &Value::Int(2));
+    }
+
+    #[test]
+    fn get_inputs_defaulted() {
+        let inputs = get_inputs("input str = 10; input name: String;").unwrap();
+
+        assert_eq!(inputs[0].name, "str");
+        assert_eq!(inputs[0].kind, ValueType::Any);
+        assert_eq!(inputs[0].state, InputState::Defaulted);
+        assert_eq!(inputs[1].name, "name");
+        assert_eq!(inputs[1].kind, ValueType::String);
+        assert_eq!(inputs[1].state, InputState::Unset);
+
+        // read like `interpret` reads a sheet
+        let errs = get_inputs(r#"use "b.plm" as b; input x;"#).unwrap_err();
+        assert!(matches!(&errs[..], [Error::ImportError { .. }]));
+
+        let errs = get_inputs("input x = 'é' +").unwrap_err();
+        assert!(matches!(&errs[..], [Error::ParsingError(e)] if e.span() == (15..16)));
+    }
+
+    #[test]
+    fn interpret_annotations() {
+        let source = r#"
+            #[group("Scores")]
+            scores {
+                #[label("Strength"), order(1)]
+                input str = 10;
+            }
+            #[units("hp")] out hp = scores.str * 2;"#;
+
+        let store = interpret(source).unwrap();
+
+        assert_eq!(
+            store
+                .annotations("scores.str")
+                .unwrap()
+                .get("label")
+                .unwrap(),
+            &Value::String("Strength".to_owned())
+        );
+        assert_eq!(
+            store.annotations("scores").unwrap().get("group").unwrap(),
+            &Value::String("Scores".to_owned())
+        );
+        assert_eq!(
+            store.annotations("hp").unwrap().get("units").unwrap(),
+            &Value::String("hp".to_owned())
+        );
+
+        let inputs = get_inputs(source).unwrap();
+
+        assert_eq!(inputs[0].annotations.get("order").unwrap(), &Value::Int(1));
+    }
+
+    #[test]
+    fn interpret_record() {
+        let interpreted = interpret(
+            "total = rope.weight * 2 + gear.items.rope.cost;
+            rope = { name: 'Rope', weight: 10, cost: 1 };
+            gear { items = { rope: rope }; }",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("total").unwrap(), &Value::Int(21));
+    }
+
+    #[test]
+    fn interpret_function() {
+        let interpreted = interpret(
+            "total = modifier(score) + bonus.apply(2);
+            fn modifier(score) = floor((score - 10) / 2);
+            score = 15;
+            bonus {
+                amount = 3;
+                fn apply(x) = x + amount;
+            }
+            fn fact(n) = if n <= 1 { 1 } else { n * fact(n - 1) };
+            out big = fact(5);",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("total").unwrap(), &Value::Int(7));
+        assert_eq!(interpreted.get("big").unwrap(), &Value::Int(120));
+    }
+
+    #[test]
+    fn interpret_function_recursion_limit() {
+        match interpret("fn forever(n) = forever(n + 1); x = forever(0);") {
+            Err(errs) => assert!(matches!(errs[0], Error::CallDepthError { .. })),
+            Ok(_) => panic!("expected a call depth error"),
+        }
+
+        // deep enough to overflow the stack if it weren't grown
+        let store =
+            interpret("fn f(n) = if n <= 0 { 0 } else { 1 + f(n - 1) }; x = f(60);").unwrap();
+        assert_eq!(store.values.get("x").unwrap(), &Value::Int(60));
+    }
+
+    #[test]
+    fn interpret_closure() {
+        let mut store = interpret(
+            "input level = 1;
+            scale = |x| x * level;
+            damage = scale(10);",
+        )
+        .unwrap();
+
+        assert_eq!(store.values.get("damage").unwrap(), &Value::Int(10));
+
+        store.set_input("level", "3").unwrap();
+        assert_eq!(store.values.get("damage").unwrap(), &Value::Int(30));
+    }
+
+    #[test]
+    fn interpret_comprehension() {
+        let interpreted = interpret(
+            "items = [
+                { name: 'Rope', weight: 10, carried: true },
+                { name: 'Tent', weight: 20, carried: false },
+                { name: 'Torch', weight: 1, carried: true }
+            ];
+            carried = sum([item.weight for item in items if item.carried]);
+            skills { athletics = true; stealth = false; arcana = true; }
+            proficient = count([skills.athletics, skills.stealth, skills.arcana]);
+            heaviest = sort(items, |item| -item.weight)[0].name;",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("carried").unwrap(), &Value::Int(11));
+        assert_eq!(interpreted.get("proficient").unwrap(), &Value::Int(2));
+        assert_eq!(
+            interpreted.get("heaviest").unwrap(),
+            &Value::String("Tent".to_owned())
+        );
+    }
+
+    #[test]
+    fn interpret_format() {
+        let interpreted = interpret(
+            "out label = \"STR {scores.str} ({modifier:+})\";
+            modifier = floor((scores.str - 10) / 2);
+            scores { str = 16; }",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(
+            interpreted.get("label").unwrap(),
+            &Value::String("STR 16 (+3)".to_owned())
+        );
+    }
+
+    #[test]
+    fn interpret_match() {
+        let mut store = interpret(
+            "input level = 1;
+            proficiency = match level { 1..=4 => 2, 5..=8 => 3, 9..=12 => 4, 13..=16 => 5, _ => 6 };",
+        )
+        .unwrap();
+
+        assert_eq!(store.values.get("proficiency").unwrap(), &Value::Int(2));
+
+        store.set_input("level", "9").unwrap();
+        assert_eq!(store.values.get("proficiency").unwrap(), &Value::Int(4));
+    }
+
+    #[test]
+    fn interpret_let() {
+        let interpreted = interpret(
+            "attack = { let base = str + prof; base * 2 };
+            damage = { let base = str * 3; let str = 1; base + str };
+            str = 4;
+            prof = 2;",
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("attack").unwrap(), &Value::Int(12));
+        assert_eq!(interpreted.get("damage").unwrap(), &Value::Int(13));
+        assert!(!interpreted.contains_key("base"));
+    }
+
+    #[test]
+    fn interpret_use() {
+        let interpreted = interpret_files(
+            "sheet.plm",
+            &[
+                (
+                    "sheet.plm",
+                    r#"use "rules/core.plm" as core; attack = core.prof + str; str = 3;"#,
+                ),
+                (
+                    "rules/core.plm",
+                    r#"use "dice.plm" as dice; prof = 2; roll = dice.sides;"#,
+                ),
+                ("rules/dice.plm", "sides = 20;"),
+            ],
+        )
+        .unwrap()
+        .values;
+
+        assert_eq!(interpreted.get("attack").unwrap(), &Value::Int(5));
+        assert_eq!(interpreted.get("core.roll").unwrap(), &Value::Int(20));
+        assert_eq!(interpreted.get("core.dice.sides").unwrap(), &Value::Int(20));
+    }
+
+    #[test]
+    fn interpret_use_errors() {
+        let errs = interpret_files(
+            "a.plm",
+            &[
+                ("a.plm", r#"use "b.plm" as b; x = 1;"#),
+                ("b.plm", r#"use "a.plm" as a; y = 2;"#),
+            ],
+        )
+        .unwrap_err();
+
+        assert!(
+            matches!(&errs[..], [Error::ImportError { path, span, .. }] if path == "a.plm" && span.start > 0)
+        );
+
+        let errs =
+            interpret_files("a.plm", &[("a.plm", r#"use "missing.plm" as m;"#)]).unwrap_err();
+        assert!(matches!(&errs[..], [Error::ImportError { path, .. }] if path == "missing.plm"));
+
+        let errs = interpret(r#"use "b.plm" as b;"#).unwrap_err();
+        assert!(matches!(&errs[..], [Error::ImportError { .. }]));
+
+        let mut sources = SourceMap::default();
+        let errs = interpret_with("missing.plm", &mut sources, &mut |_| {
+            Err(io::Error::new(io::ErrorKind::NotFound, "file not found"))
+        })
+        .unwrap_err();
+
+        let [Error::ImportError { span, .. }] = &errs[..] else {
+            panic!("expected an import error, got {:?}", errs);
+        };
+        assert_eq!(sources.locate(span.clone()).0, "missing.plm");
+    }
 }
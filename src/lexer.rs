@@ -1,6 +1,9 @@
 use chumsky::prelude::*;
 
-use crate::ast::{Span, Token};
+use crate::{
+    ast::{Span, StringPart, Token},
+    value::ValueType,
+};
 
 pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
     // numbers
@@ -10,9 +13,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
         .chain(just('+').or(just('-')).or_not())
         .chain::<char, _, _>(text::digits(10));
 
-    let num = just('-')
-        .or_not()
-        .chain::<char, _, _>(text::int(10))
+    let num = text::int(10)
         .chain::<char, _, _>(frac.or_not().flatten())
         .chain::<char, _, _>(exp.or_not().flatten())
         .collect::<String>()
@@ -20,6 +21,14 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
         .labelled("num")
         .boxed();
 
+    // dice, like 2d6
+    let dice = text::int(10)
+        .chain::<char, _, _>(just('d'))
+        .chain::<char, _, _>(text::int(10))
+        .collect::<String>()
+        .map(Token::Dice)
+        .labelled("dice");
+
     // strings
     let escape = just('\\').ignore_then(
         choice((
@@ -48,54 +57,81 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
         ),
     );
 
-    // the d stands for double quotes
-    let d_string = just('"')
-        .ignore_then(filter(|c| *c != '\\' && *c != '"').or(escape).repeated())
-        .then_ignore(just('"'))
-        .collect::<String>();
-
-    // the s stands for single quotes
-    let s_string = just('\'')
-        .ignore_then(filter(|c| *c != '\\' && *c != '\'').or(escape).repeated())
-        .then_ignore(just('\''))
-        .collect::<String>();
+    // the d stands for double quotes, and the s for single quotes
+    let d_string = string('"', escape);
+    let s_string = string('\'', escape);
 
-    let string = d_string.or(s_string).map(Token::String).labelled("string");
+    let string = d_string.or(s_string).labelled("string");
 
     // operators
-    let op = one_of("+-*/!=<>&|.%@$")
-        .repeated()
-        .at_least(1)
-        .collect::<String>()
-        .map(Token::Op);
+    // longer operators go first, so `**` isn't lexed as two `*`s
+    let op = choice((
+        just("..="),
+        just("**"),
+        just("=="),
+        just("=>"),
+        just("!="),
+        just("<="),
+        just(">="),
+        just("&&"),
+        just("||"),
+        just("|"),
+        just(".."),
+        just("+"),
+        just("-"),
+        just("*"),
+        just("/"),
+        just("%"),
+        just("!"),
+        just("<"),
+        just(">"),
+        just("="),
+        just("."),
+        just("@"),
+        just("$"),
+    ))
+    .map(|op: &str| Token::Op(op.to_owned()));
 
     // control characters
-    let ctrl = one_of("()[]{};:,").map(|c| Token::Ctrl(c));
+    let ctrl = one_of("()[]{};:,#").map(|c| Token::Ctrl(c));
 
-    // identifiers
+    // identifiers, and keywords which are only ever whole words, so `inputs` or `integer` are still names
     let ident = text::ident().map(|ident: String| match ident.as_str() {
+        "input" => Token::Input,
         "if" => Token::If,
         "else" => Token::Else,
+        "where" => Token::Where,
+        "pub" => Token::Pub,
+        "out" => Token::Out,
+        "fn" => Token::Fn,
+        "for" => Token::For,
+        "match" => Token::Match,
+        "let" => Token::Let,
+        "use" => Token::Use,
+        "as" => Token::As,
         "true" => Token::Bool(true),
         "false" => Token::Bool(false),
+        "null" => Token::Null,
         "in" => Token::Op("in".to_owned()),
+        "by" => Token::Op("by".to_owned()),
+        "and" => Token::Op("and".to_owned()),
+        "or" => Token::Op("or".to_owned()),
+        "not" => Token::Op("not".to_owned()),
+        // `//` starts a comment, so integer division gets words instead
+        "div" => Token::Op("div".to_owned()),
+        "rdiv" => Token::Op("rdiv".to_owned()),
+        "ediv" => Token::Op("ediv".to_owned()),
+        _ if ValueType::named(&ident).is_some() => Token::Type(ident),
         _ => Token::Ident(ident),
     });
 
-    // input variable
-    let input = just("input").to(Token::Input);
-
-    // type names
-    let kind = choice::<_, Simple<char>>((
-        just("float"),
-        just("int"),
-        just("String"),
-        just("bool"),
-    ))
-    .map(|name: &str| Token::Type(name.to_owned()));
+    // macros like `common!`, but not `a != b`
+    let macro_name = text::ident()
+        .then_ignore(just('!').then_ignore(none_of("=").rewind()))
+        .map(Token::Macro);
 
-    let token =
-        choice((num, kind, input, string, op, ctrl, ident)).recover_with(skip_then_retry_until([]));
+    let token = choice((dice, num, macro_name, string, op, ctrl, ident))
+        .recover_with(skip_then_retry_until([]));
 
     let comment = just("//").then(take_until(just('\n'))).padded();
 
@@ -106,11 +142,132 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Simple<char>> {
         .repeated()
 }
 
+const STRAY_BRACE: &str = "A `}` on its own has to be written `}}`, and an interpolation starting with `{` needs a space first, like `{ {a: 1}.a }`";
+
+// a string with the given quotes, where `{..}` interpolates and `{{` or `}}` are literal braces
+fn string(
+    quote: char,
+    escape: impl Parser<char, char, Error = Simple<char>> + Clone,
+) -> impl Parser<char, Token, Error = Simple<char>> + Clone {
+    let text = filter(move |c: &char| *c != '\\' && *c != quote && *c != '{' && *c != '}')
+        .or(escape)
+        .or(just('{').then(just('{')).to('{'))
+        // `{{` is always a literal brace, so `"{{a: 1}.a}"` ends up with a `}` on its own
+        .or(just('}')
+            .then(just('}').or_not())
+            .try_map(|(_, second), span: Span| match second {
+                Some(_) => Ok('}'),
+                None => Err(Simple::custom(span, STRAY_BRACE)),
+            }))
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .map(StringPart::Text);
+
+    // strings inside an interpolation use the other quotes, and braces in them don't count here
+    // (they are lexed again with the interpolation, so a literal `}` in them is still `}}`)
+    let other = if quote == '"' { '\'' } else { '"' };
+    let inner_string = just(other)
+        .chain(
+            just('\\')
+                .chain(any())
+                .or(filter(move |c: &char| *c != other && *c != '\\').map(|c| vec![c]))
+                .repeated()
+                .flatten(),
+        )
+        .chain(just(other));
+
+    // the source of an interpolation, which can have braces of its own
+    let balanced = recursive(|balanced| {
+        filter(move |c: &char| *c != '{' && *c != '}' && *c != quote && *c != other)
+            .map(|c| vec![c])
+            .or(inner_string)
+            .or(just('{').chain(balanced).chain(just('}')))
+            .repeated()
+            .flatten()
+    });
+
+    let interpolation = just('{')
+        .ignore_then(balanced.map_with_span(|source: Vec<char>, span: Span| (source, span.start)))
+        .then_ignore(just('}'))
+        .map(|(source, start)| {
+            let (source, spec) = split_spec(&source);
+
+            StringPart::Interpolation {
+                source,
+                spec,
+                offset: start,
+            }
+        });
+
+    text.or(interpolation)
+        .repeated()
+        .delimited_by(just(quote), just(quote))
+        .map_with_span(|parts: Vec<StringPart>, span: Span| {
+            if parts.iter().all(|part| matches!(part, StringPart::Text(_))) {
+                let text = parts
+                    .into_iter()
+                    .map(|part| match part {
+                        StringPart::Text(e) => e,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+
+                return Token::String(text);
+            }
+
+            let parts = parts
+                .into_iter()
+                .map(|part| match part {
+                    StringPart::Interpolation {
+                        source,
+                        spec,
+                        offset,
+                    } => StringPart::Interpolation {
+                        source,
+                        spec,
+                        offset: offset - span.start,
+                    },
+                    text => text,
+                })
+                .collect();
+
+            Token::Format(parts)
+        })
+}
+
+// `modifier:+` into the source and the format spec, going by the last `:` outside of any brackets
+fn split_spec(source: &[char]) -> (String, Option<String>) {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut split = None;
+
+    for (idx, c) in source.iter().enumerate() {
+        match (quote, c) {
+            (Some(q), c) if q == *c => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(*c),
+            (None, '(' | '[' | '{') => depth += 1,
+            (None, ')' | ']' | '}') => depth -= 1,
+            (None, ':') if depth == 0 => split = Some(idx),
+            _ => {}
+        }
+    }
+
+    match split {
+        Some(idx) => (
+            source[..idx].iter().collect(),
+            Some(source[idx + 1..].iter().collect()),
+        ),
+        None => (source.iter().collect(), None),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use chumsky::Parser;
 
-    use crate::ast::Token;
+    use crate::ast::{StringPart, Token};
 
     use super::lexer;
 
@@ -247,4 +404,124 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn lex_keyword_boundaries() {
+        let lexed = lexer().parse("inputs integer Int Intx").unwrap();
+
+        assert_eq!(
+            lexed,
+            vec![
+                (Token::Ident("inputs".to_owned()), 0..6),
+                (Token::Ident("integer".to_owned()), 7..14),
+                (Token::Type("Int".to_owned()), 15..18),
+                (Token::Ident("Intx".to_owned()), 19..23)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_dice() {
+        let lexed = lexer().parse("2d6 + 3").unwrap();
+
+        assert_eq!(
+            lexed,
+            vec![
+                (Token::Dice("2d6".to_owned()), 0..3),
+                (Token::Op("+".to_owned()), 4..5),
+                (Token::Num("3".to_owned()), 6..7)
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_macro() {
+        let lexed = lexer().parse("ident!() a != b").unwrap();
+
+        assert_eq!(
+            lexed,
+            vec![
+                (Token::Macro("ident".to_owned()), 0..6),
+                (Token::Ctrl('('), 6..7),
+                (Token::Ctrl(')'), 7..8),
+                (Token::Ident("a".to_owned()), 9..10),
+                (Token::Op("!=".to_owned()), 11..13),
+                (Token::Ident("b".to_owned()), 14..15)
+            ]
+        )
+    }
+
+    #[test]
+    fn lex_unary() {
+        let lexed = lexer().parse("x-1*-y").unwrap();
+
+        assert_eq!(
+            lexed,
+            vec![
+                (Token::Ident("x".to_owned()), 0..1),
+                (Token::Op("-".to_owned()), 1..2),
+                (Token::Num("1".to_owned()), 2..3),
+                (Token::Op("*".to_owned()), 3..4),
+                (Token::Op("-".to_owned()), 4..5),
+                (Token::Ident("y".to_owned()), 5..6)
+            ]
+        )
+    }
+
+    #[test]
+    fn lex_format() {
+        let lexed = lexer()
+            .parse("\"STR {score} ({mod:+}) {{}}\" 'plain {{'")
+            .unwrap();
+
+        assert_eq!(
+            lexed,
+            vec![
+                (
+                    Token::Format(vec![
+                        StringPart::Text("STR ".to_owned()),
+                        StringPart::Interpolation {
+                            source: "score".to_owned(),
+                            spec: None,
+                            offset: 6,
+                        },
+                        StringPart::Text(" (".to_owned()),
+                        StringPart::Interpolation {
+                            source: "mod".to_owned(),
+                            spec: Some("+".to_owned()),
+                            offset: 15,
+                        },
+                        StringPart::Text(") {}".to_owned()),
+                    ]),
+                    0..28
+                ),
+                (Token::String("plain {".to_owned()), 29..39)
+            ]
+        )
+    }
+
+    #[test]
+    fn lex_format_nested_braces() {
+        let lexed = lexer().parse(r#""{ {a: '}}'}.a }""#).unwrap();
+
+        assert_eq!(
+            lexed,
+            vec![(
+                Token::Format(vec![StringPart::Interpolation {
+                    source: " {a: '}}'}.a ".to_owned(),
+                    spec: None,
+                    offset: 2,
+                }]),
+                0..17
+            )]
+        );
+
+        // `{{` is a literal brace, so the `}` after the record is on its own
+        let errs = lexer().parse(r#""{{a: 1}.a}""#).unwrap_err();
+        assert_eq!(errs[0].span(), 7..8);
+        assert!(matches!(
+            errs[0].reason(),
+            chumsky::error::SimpleReason::Custom(_)
+        ));
+    }
 }
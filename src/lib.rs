@@ -1,12 +1,25 @@
+// errors carry the offending value along for reporting, so they're big on purpose
+#![allow(clippy::result_large_err)]
+
 mod ast;
+pub mod builtins;
 mod codegen;
+pub mod dice;
 pub mod error;
 pub mod eval;
+pub mod format;
+pub mod function;
+mod imports;
 pub mod inputs;
 pub mod interpreter;
 pub mod lexer;
 pub mod operators;
+pub mod outputs;
 pub mod parser;
+mod patterns;
+pub mod range;
+pub mod sources;
+mod templates;
 pub mod value;
 
-pub use ast::Spanned;
+pub use ast::{Modifiers, Spanned};
@@ -1,84 +1,189 @@
+use std::{
+    cmp::Ordering,
+    ops::{Neg, Range},
+};
+
 use crate::{
     ast::InfixOp,
+    dice::Dice,
     error::{Error, TypeErrorCtx},
-    value::{SpannedValue, Value, ValueType},
+    range::IntRange,
+    value::{SpannedValue, Value, ValueType, MAX_ITEMS},
 };
 
+// dice can only be offset by whole numbers
+fn dice_int(value: &SpannedValue, context: TypeErrorCtx) -> Result<i64, Error> {
+    match value.0 {
+        Value::Int(e) => Ok(e),
+        _ => Err(Error::TypeError {
+            expected: ValueType::Int.into(),
+            got: value.clone(),
+            context,
+        }),
+    }
+}
+
+// the result of dice arithmetic, which only fails when it's too big
+fn dice(dice: Option<Dice>, span: &Range<usize>) -> Result<Value, Error> {
+    dice.map(Value::Dice)
+        .ok_or_else(|| Error::OverflowError { span: span.clone() })
+}
+
+// both sides of an arithmetic operator, as ints if they both are and as floats otherwise
+enum Numbers {
+    Int(i64, i64),
+    Num(f64, f64),
+}
+
+fn numbers(lhs: &Value, rhs: &Value) -> Option<Numbers> {
+    match (lhs, rhs) {
+        (Value::Int(lhs), Value::Int(rhs)) => Some(Numbers::Int(*lhs, *rhs)),
+        _ => Some(Numbers::Num(lhs.as_num()?, rhs.as_num()?)),
+    }
+}
+
+// int results that don't fit are errors, rather than wrapping around
+fn checked(result: Option<i64>, lhs: &SpannedValue, rhs: &SpannedValue) -> Result<Value, Error> {
+    match result {
+        Some(e) => Ok(Value::Int(e)),
+        None => Err(Error::OverflowError {
+            span: lhs.1.start..rhs.1.end,
+        }),
+    }
+}
+
+// for operators that only work on numbers
+fn not_numbers(lhs: SpannedValue, op: InfixOp, rhs: SpannedValue) -> Error {
+    match lhs.0.as_num() {
+        Some(_) => Error::TypeError {
+            expected: ValueType::Num.into(),
+            got: rhs,
+            context: TypeErrorCtx::InfixOpRhs {
+                lhs: lhs.0.get_type(),
+                op,
+            },
+        },
+        None => Error::TypeError {
+            expected: ValueType::Num.into(),
+            got: lhs,
+            context: TypeErrorCtx::InfixOpLhs { op },
+        },
+    }
+}
+
+// rounds towards negative infinity, unlike `/` on ints
+fn floor_div(lhs: i128, rhs: i128) -> i128 {
+    let quotient = lhs / rhs;
+
+    if lhs % rhs != 0 && (lhs < 0) != (rhs < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+// negative indices count back from the end
+fn normalize(index: isize, len: usize) -> Option<usize> {
+    if index < 0 {
+        len.checked_sub(index.unsigned_abs())
+    } else {
+        Some(index as usize)
+    }
+}
+
+// the indices a range picks out of something `len` long, in order
+// a range that counts down, like `3..0`, picks them out backwards
+fn slice(range: &IntRange, len: usize) -> Option<Vec<usize>> {
+    let normalized = IntRange {
+        start: normalize(range.start as isize, len)? as i64,
+        end: normalize(range.end as isize, len)? as i64,
+        ..range.clone()
+    };
+    let start = normalized.start as usize;
+
+    match normalized.last() {
+        // nothing picked out just past the last item is still in bounds
+        None if start <= len => Some(Vec::new()),
+        Some(last) if start < len && (last as usize) < len => {
+            Some(normalized.items().map(|i| i as usize).collect())
+        }
+        _ => None,
+    }
+}
+
+// a string repeated `count` times, as long as the result isn't too big
+fn repeated(string: &str, value: &SpannedValue) -> Result<Value, Error> {
+    let count = match value.0 {
+        Value::Int(e) => e.max(0) as u128,
+        _ => {
+            return Err(Error::TypeError {
+                expected: ValueType::Int.into(),
+                got: value.clone(),
+                context: TypeErrorCtx::StringMul,
+            })
+        }
+    };
+
+    let len = string.chars().count() as u128 * count;
+
+    if len > MAX_ITEMS {
+        return Err(Error::SizeError {
+            len,
+            span: value.1.clone(),
+        });
+    }
+
+    Ok(Value::String(string.repeat(count as usize)))
+}
+
 // da big SpannedValue operation set
 impl SpannedValue {
     pub fn pow(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => {
-                    if lhs == lhs.trunc() && rhs == rhs.trunc() {
-                        Ok(Value::Num(lhs.powi(rhs as i32)))
-                    } else {
-                        Ok(Value::Num(lhs.powf(rhs)))
-                    }
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) if rhs >= 0 => {
+                let result = u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_pow(rhs));
+
+                checked(result, &self, &other)
+            }
+            Some(Numbers::Int(lhs, rhs)) => Ok(Value::Num((lhs as f64).powf(rhs as f64))),
+            Some(Numbers::Num(lhs, rhs)) => {
+                if rhs == rhs.trunc() {
+                    Ok(Value::Num(lhs.powi(rhs as i32)))
+                } else {
+                    Ok(Value::Num(lhs.powf(rhs)))
                 }
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Pow,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Pow },
-            }),
+            }
+            None => Err(not_numbers(self, InfixOp::Pow, other)),
         }
     }
 
     pub fn mul(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            // idk why i have to clone here but it wont compile if i dont
-            Value::Num(lhs) => match other.0.clone() {
-                Value::Num(rhs) => Ok(Value::Num(lhs * rhs)),
-                Value::String(rhs) => {
-                    if lhs == lhs.trunc() {
-                        Ok(Value::String(rhs.repeat(lhs as usize)))
-                    } else {
-                        Err(Error::TypeError {
-                            expected: ValueType::Int.into(),
-                            got: other,
-                            context: TypeErrorCtx::StringMul,
-                        })
-                    }
-                }
-                _ => Err(Error::TypeError {
-                    expected: vec![ValueType::Num, ValueType::String],
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Mul,
-                    },
-                }),
-            },
-            Value::String(lhs) => match other.0 {
-                Value::Num(rhs) => {
-                    if rhs == rhs.trunc() {
-                        Ok(Value::String(lhs.repeat(rhs as usize)))
-                    } else {
-                        Err(Error::TypeError {
-                            expected: ValueType::Int.into(),
-                            got: other,
-                            context: TypeErrorCtx::StringMul,
-                        })
-                    }
-                }
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::String,
-                        op: InfixOp::Mul,
-                    },
-                }),
-            },
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => return checked(lhs.checked_mul(rhs), &self, &other),
+            Some(Numbers::Num(lhs, rhs)) => return Ok(Value::Num(lhs * rhs)),
+            None => {}
+        }
+
+        // dice can't be multiplied, since `2 * 2d6` would have to roll 2d6 once and double it rather than roll 4d6
+        match (&self.0, &other.0) {
+            (Value::Int(_) | Value::Num(_), Value::String(rhs)) => repeated(rhs, &self),
+            (Value::Int(_) | Value::Num(_), _) => Err(Error::TypeError {
+                expected: vec![ValueType::Num, ValueType::String],
+                got: other,
+                context: TypeErrorCtx::InfixOpRhs {
+                    lhs: self.0.get_type(),
+                    op: InfixOp::Mul,
+                },
+            }),
+            (Value::String(lhs), Value::Int(_) | Value::Num(_)) => repeated(lhs, &other),
+            (Value::String(_), _) => Err(Error::TypeError {
+                expected: ValueType::Int.into(),
+                got: other,
+                context: TypeErrorCtx::InfixOpRhs {
+                    lhs: ValueType::String,
+                    op: InfixOp::Mul,
+                },
+            }),
             _ => Err(Error::TypeError {
                 expected: vec![ValueType::Num, ValueType::String],
                 got: self,
@@ -87,63 +192,98 @@ impl SpannedValue {
         }
     }
 
+    // `/` is always a `Num`, even for ints that divide evenly, so its type doesn't depend on the values
+    // `div`, `rdiv` and `ediv` are there for a whole `Int`
     pub fn div(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Num(lhs / rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Div,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Div },
-            }),
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(_, 0)) => Err(Error::ZeroDivisionError { span: other.1 }),
+            Some(Numbers::Int(lhs, rhs)) => Ok(Value::Num(lhs as f64 / rhs as f64)),
+            Some(Numbers::Num(lhs, rhs)) => Ok(Value::Num(lhs / rhs)),
+            None => Err(not_numbers(self, InfixOp::Div, other)),
+        }
+    }
+
+    // `div`, `rdiv` and `ediv`, so ints don't have to go through floats to be rounded
+    pub fn rounded_div(self, other: Self, op: InfixOp) -> Result<Value, Error> {
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(_, 0)) => Err(Error::ZeroDivisionError { span: other.1 }),
+            Some(Numbers::Int(lhs, rhs)) => {
+                // wide enough that doubling either side can't overflow
+                let (lhs, rhs) = (lhs as i128, rhs as i128);
+
+                let out = match op {
+                    InfixOp::RoundDiv => floor_div(2 * lhs + rhs, 2 * rhs),
+                    InfixOp::EvenDiv => {
+                        let quotient = floor_div(lhs, rhs);
+                        let remainder = (lhs - quotient * rhs).abs() * 2;
+
+                        match remainder.cmp(&rhs.abs()) {
+                            Ordering::Less => quotient,
+                            Ordering::Greater => quotient + 1,
+                            Ordering::Equal => quotient + quotient.rem_euclid(2),
+                        }
+                    }
+                    _ => floor_div(lhs, rhs),
+                };
+
+                checked(i64::try_from(out).ok(), &self, &other)
+            }
+            Some(Numbers::Num(lhs, rhs)) => {
+                let out = match op {
+                    InfixOp::RoundDiv => (lhs / rhs + 0.5).floor(),
+                    InfixOp::EvenDiv => (lhs / rhs).round_ties_even(),
+                    _ => (lhs / rhs).floor(),
+                };
+
+                Ok(Value::Num(out))
+            }
+            None => Err(not_numbers(self, op, other)),
         }
     }
 
     pub fn modulus(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Num(lhs % rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Mod,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Mod },
-            }),
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(_, 0)) => Err(Error::ZeroDivisionError { span: other.1 }),
+            Some(Numbers::Int(lhs, rhs)) => checked(lhs.checked_rem(rhs), &self, &other),
+            Some(Numbers::Num(lhs, rhs)) => Ok(Value::Num(lhs % rhs)),
+            None => Err(not_numbers(self, InfixOp::Mod, other)),
         }
     }
 
     pub fn add(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Num(lhs + rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Add,
-                    },
-                }),
-            },
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => return checked(lhs.checked_add(rhs), &self, &other),
+            Some(Numbers::Num(lhs, rhs)) => return Ok(Value::Num(lhs + rhs)),
+            None => {}
+        }
+
+        match (&self.0, &other.0) {
+            (Value::Int(_) | Value::Num(_), Value::Dice(rhs)) => {
+                let lhs = dice_int(&self, TypeErrorCtx::InfixOpLhs { op: InfixOp::Add })?;
+
+                dice(rhs.clone().add_flat(lhs), &self.1)
+            }
+            (Value::Int(_) | Value::Num(_), _) => Err(Error::TypeError {
+                expected: vec![ValueType::Num, ValueType::Dice],
+                got: other,
+                context: TypeErrorCtx::InfixOpRhs {
+                    lhs: self.0.get_type(),
+                    op: InfixOp::Add,
+                },
+            }),
+            (Value::Dice(lhs), Value::Dice(rhs)) => {
+                dice(lhs.clone().checked_add(rhs.clone()), &other.1)
+            }
+            (Value::Dice(lhs), _) => {
+                let context = TypeErrorCtx::InfixOpRhs {
+                    lhs: ValueType::Dice,
+                    op: InfixOp::Add,
+                };
+
+                dice(lhs.clone().add_flat(dice_int(&other, context)?), &other.1)
+            }
             _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
+                expected: vec![ValueType::Num, ValueType::Dice],
                 got: self,
                 context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Add },
             }),
@@ -151,20 +291,46 @@ impl SpannedValue {
     }
 
     pub fn sub(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Num(lhs - rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Sub,
-                    },
-                }),
-            },
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => return checked(lhs.checked_sub(rhs), &self, &other),
+            Some(Numbers::Num(lhs, rhs)) => return Ok(Value::Num(lhs - rhs)),
+            None => {}
+        }
+
+        match (&self.0, &other.0) {
+            (Value::Int(_) | Value::Num(_), Value::Dice(rhs)) => {
+                let lhs = dice_int(&self, TypeErrorCtx::InfixOpLhs { op: InfixOp::Sub })?;
+
+                dice(
+                    rhs.clone().negate().and_then(|rhs| rhs.add_flat(lhs)),
+                    &self.1,
+                )
+            }
+            (Value::Int(_) | Value::Num(_), _) => Err(Error::TypeError {
+                expected: vec![ValueType::Num, ValueType::Dice],
+                got: other,
+                context: TypeErrorCtx::InfixOpRhs {
+                    lhs: self.0.get_type(),
+                    op: InfixOp::Sub,
+                },
+            }),
+            (Value::Dice(lhs), Value::Dice(rhs)) => dice(
+                rhs.clone()
+                    .negate()
+                    .and_then(|rhs| lhs.clone().checked_add(rhs)),
+                &other.1,
+            ),
+            (Value::Dice(lhs), _) => {
+                let context = TypeErrorCtx::InfixOpRhs {
+                    lhs: ValueType::Dice,
+                    op: InfixOp::Sub,
+                };
+                let rhs = dice_int(&other, context)?.checked_neg();
+
+                dice(rhs.and_then(|rhs| lhs.clone().add_flat(rhs)), &other.1)
+            }
             _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
+                expected: vec![ValueType::Num, ValueType::Dice],
                 got: self,
                 context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Sub },
             }),
@@ -172,86 +338,34 @@ impl SpannedValue {
     }
 
     pub fn lt(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Bool(lhs < rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Lt,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Lt },
-            }),
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => Ok(Value::Bool(lhs < rhs)),
+            Some(Numbers::Num(lhs, rhs)) => Ok(Value::Bool(lhs < rhs)),
+            None => Err(not_numbers(self, InfixOp::Lt, other)),
         }
     }
 
     pub fn gt(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Bool(lhs > rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Gt,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Gt },
-            }),
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => Ok(Value::Bool(lhs > rhs)),
+            Some(Numbers::Num(lhs, rhs)) => Ok(Value::Bool(lhs > rhs)),
+            None => Err(not_numbers(self, InfixOp::Gt, other)),
         }
     }
 
     pub fn lte(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Bool(lhs <= rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Lte,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Lte },
-            }),
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => Ok(Value::Bool(lhs <= rhs)),
+            Some(Numbers::Num(lhs, rhs)) => Ok(Value::Bool(lhs <= rhs)),
+            None => Err(not_numbers(self, InfixOp::Lte, other)),
         }
     }
 
     pub fn gte(self, other: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(lhs) => match other.0 {
-                Value::Num(rhs) => Ok(Value::Bool(lhs >= rhs)),
-                _ => Err(Error::TypeError {
-                    expected: ValueType::Num.into(),
-                    got: other,
-                    context: TypeErrorCtx::InfixOpRhs {
-                        lhs: ValueType::Num,
-                        op: InfixOp::Gte,
-                    },
-                }),
-            },
-            _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::InfixOpLhs { op: InfixOp::Gte },
-            }),
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => Ok(Value::Bool(lhs >= rhs)),
+            Some(Numbers::Num(lhs, rhs)) => Ok(Value::Bool(lhs >= rhs)),
+            None => Err(not_numbers(self, InfixOp::Gte, other)),
         }
     }
 
@@ -298,16 +412,26 @@ impl SpannedValue {
     }
 
     pub fn equals(self, other: Self) -> Result<Value, Error> {
+        match numbers(&self.0, &other.0) {
+            Some(Numbers::Int(lhs, rhs)) => return Ok(Value::Bool(lhs == rhs)),
+            Some(Numbers::Num(lhs, rhs)) => return Ok(Value::Bool(lhs == rhs)),
+            None => {}
+        }
+
         match (self.0.clone(), other.0.clone()) {
-            (Value::Num(lhs), Value::Num(rhs)) => Ok(Value::Bool(lhs == rhs)),
             (Value::String(lhs), Value::String(rhs)) => Ok(Value::Bool(lhs == rhs)),
             (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(lhs == rhs)),
-            (Value::Array(lhs), Value::Array(rhs)) => Ok(Value::Bool(lhs == rhs)),
+            (lhs @ Value::Array(_), rhs @ Value::Array(_)) => Ok(Value::Bool(lhs.same(&rhs))),
+            (Value::Dice(lhs), Value::Dice(rhs)) => Ok(Value::Bool(lhs == rhs)),
+            (lhs @ Value::Record(_), rhs @ Value::Record(_)) => Ok(Value::Bool(lhs.same(&rhs))),
+            // anything can be checked against null
+            (Value::Null, rhs) => Ok(Value::Bool(rhs == Value::Null)),
+            (lhs, Value::Null) => Ok(Value::Bool(lhs == Value::Null)),
             _ => {
                 let lhs = self.0.get_type();
 
                 Err(Error::TypeError {
-                    expected: lhs.into(),
+                    expected: lhs.clone().into(),
                     got: other,
                     context: TypeErrorCtx::InfixOpRhs {
                         lhs,
@@ -324,8 +448,36 @@ impl SpannedValue {
     }
 
     pub fn contains(self, other: Self) -> Result<Value, Error> {
-        let yes = match other.0 {
-            Value::Array(lhs) => lhs.contains(&SpannedValue(self.0, 0..1)),
+        let yes = match other.0.clone() {
+            Value::Array(lhs) => lhs.iter().any(|item| item.0.same(&self.0)),
+            // whether a record has a field
+            Value::Record(lhs) => match self.0 {
+                Value::String(rhs) => lhs.contains_key(&rhs),
+                _ => {
+                    return Err(Error::TypeError {
+                        expected: ValueType::String.into(),
+                        got: self,
+                        context: TypeErrorCtx::InfixOpRhs {
+                            lhs: ValueType::Record,
+                            op: (InfixOp::In),
+                        },
+                    })
+                }
+            },
+            Value::Range(lhs) => match self.0 {
+                Value::Int(rhs) => lhs.contains(rhs),
+                Value::Num(rhs) => lhs.contains_num(rhs),
+                _ => {
+                    return Err(Error::TypeError {
+                        expected: ValueType::Num.into(),
+                        got: self,
+                        context: TypeErrorCtx::InfixOpRhs {
+                            lhs: ValueType::Range,
+                            op: (InfixOp::In),
+                        },
+                    })
+                }
+            },
             Value::String(lhs) => match self.0 {
                 Value::String(rhs) => lhs.contains(&rhs),
                 _ => {
@@ -341,8 +493,13 @@ impl SpannedValue {
             },
             _ => {
                 return Err(Error::TypeError {
-                    expected: vec![ValueType::Array, ValueType::String],
-                    got: self,
+                    expected: vec![
+                        ValueType::Array,
+                        ValueType::String,
+                        ValueType::Record,
+                        ValueType::Range,
+                    ],
+                    got: other,
                     context: TypeErrorCtx::InfixOpLhs { op: InfixOp::In },
                 })
             }
@@ -366,229 +523,201 @@ impl SpannedValue {
         }
     }
 
+    // `record.name`, with the span of `name`
+    pub fn field(self, name: &str, span: Range<usize>) -> Result<Value, Error> {
+        match self.0 {
+            Value::Record(ref fields) => match fields.get(name) {
+                Some(value) => Ok(value.0.clone()),
+                None => Err(Error::ReferenceError {
+                    name: name.to_owned(),
+                    span,
+                }),
+            },
+            _ => Err(Error::TypeError {
+                expected: ValueType::Record.into(),
+                got: self,
+                context: TypeErrorCtx::Access,
+            }),
+        }
+    }
+
     pub fn index(self, idx: Self) -> Result<Value, Error> {
-        let inner = self.0.clone();
+        if let Value::Record(_) = self.0 {
+            return match idx.0 {
+                Value::String(ref name) => self.field(name, idx.1.clone()),
+                _ => Err(Error::TypeError {
+                    expected: ValueType::String.into(),
+                    got: idx,
+                    context: TypeErrorCtx::Index,
+                }),
+            };
+        }
+
+        // a range is indexed without turning it into an array, since it could be huge
+        if let (Value::Range(range), Value::Int(e)) = (&self.0, &idx.0) {
+            let len = range.len() as i128;
+            let i = if *e < 0 { len + *e as i128 } else { *e as i128 };
+
+            return match range.nth(i.max(0) as u128) {
+                Some(item) if i >= 0 => Ok(Value::Int(item)),
+                _ => Err(Error::IndexError {
+                    index: *e as isize,
+                    len: usize::try_from(len).unwrap_or(usize::MAX),
+                    lspan: self.1,
+                    rspan: idx.1,
+                }),
+            };
+        }
+
+        // slicing a range picks out its numbers like it would from an array
+        if let Value::Range(_) = self.0 {
+            let items = match self.0.items(&self.1) {
+                Some(items) => items?,
+                None => unreachable!("Ranges always have items"),
+            };
 
-        let len = match &inner {
+            return SpannedValue(Value::Array(items), self.1).index(idx);
+        }
+
+        // strings are indexed by character, not by byte
+        let chars: Vec<char> = match &self.0 {
+            Value::String(f) => f.chars().collect(),
+            _ => Vec::new(),
+        };
+        let len = match &self.0 {
             Value::Array(f) => f.len(),
-            Value::String(f) => f.len(),
+            Value::String(_) => chars.len(),
             _ => {
                 return Err(Error::TypeError {
-                    expected: vec![ValueType::Array, ValueType::String],
+                    expected: vec![ValueType::Array, ValueType::String, ValueType::Range],
                     got: self,
                     context: TypeErrorCtx::IndexOf,
                 })
             }
         };
 
-        match idx.0.clone() {
-            Value::Num(e) => {
-                if e == e.trunc() {
-                    let e = e as isize;
-                    let e_abs = e.abs() as usize;
-                    let cond = if e < 0 { len >= e_abs } else { len > e_abs };
-
-                    if cond {
-                        let e2 = if e < 0 { len - e_abs } else { e_abs };
-
-                        match inner {
-                            Value::Array(f) => Ok(f[e2].clone().0),
-                            Value::String(f) => {
-                                Ok(Value::String(f.chars().nth(e2).unwrap().to_string()))
-                            }
-                            _ => unreachable!("Checked when checked length at start"),
-                        }
-                    } else {
-                        Err(Error::IndexError {
-                            index: e,
-                            len,
-                            lspan: self.1,
-                            rspan: idx.1,
-                        })
-                    }
-                } else {
-                    Err(Error::TypeError {
-                        expected: vec![ValueType::Num, ValueType::Range],
-                        got: self,
-                        context: TypeErrorCtx::Index,
-                    })
-                }
-            }
-            Value::Range(e) | Value::IRange(e) => {
-                let start = e.start;
-                let end = e.end;
-                let start_abs = start.abs() as usize;
-                let end_abs = end.abs() as usize;
-
-                let start_cond = if start < 0 {
-                    len >= start_abs
-                } else {
-                    len > start_abs
-                };
-
-                let end_cond = if end < 0 {
-                    len >= end_abs
-                } else {
-                    len > end_abs
-                };
+        let picked = match &idx.0 {
+            Value::Int(e) => {
+                let e = *e as isize;
 
-                if start_cond && end_cond {
-                    let start_norm = if start < 0 {
-                        len - start_abs
-                    } else {
-                        start_abs
-                    };
-                    let end_norm = if end < 0 { len - end_abs } else { end_abs };
-
-                    if start_norm > end_norm {
-                        match inner {
-                            Value::Array(mut f) => {
-                                f.reverse();
-                                let start_norm = len - start_norm - 1;
-                                let end_norm = len - end_norm + 1;
-
-                                let out = f[start_norm..=end_norm].to_vec();
-
-                                Ok(Value::Array(out))
-                            }
-                            Value::String(f) => {
-                                let f = f.chars().rev().collect::<String>();
-                                let start_norm = len - start_norm - 1;
-
-                                let out = if let Value::IRange(_) = idx.0 {
-                                    let end_norm = len - (end_norm + 1);
-
-                                    f[start_norm..=end_norm].to_string()
-                                } else {
-                                    let end_norm = len - end_norm - 1;
-
-                                    f[start_norm..end_norm].to_string()
-                                };
-
-                                Ok(Value::String(out))
-                            }
-                            _ => unreachable!("Checked when checked length at start"),
-                        }
-                    } else {
-                        match inner {
-                            Value::Array(f) => {
-                                let out = match idx.0 {
-                                    Value::Range(_) => f[start_norm..end_norm].to_vec(),
-                                    Value::IRange(_) => f[start_norm..=end_norm].to_vec(),
-                                    _ => unreachable!("Outermost match is one of those"),
-                                };
-
-                                Ok(Value::Array(out))
-                            }
-                            Value::String(f) => {
-                                let out = match idx.0 {
-                                    Value::Range(_) => f[start_norm..end_norm].to_string(),
-                                    Value::IRange(_) => f[start_norm..=end_norm].to_string(),
-                                    _ => unreachable!("Outermost match is one of those"),
-                                };
-
-                                Ok(Value::String(out))
-                            }
-                            _ => unreachable!("Checked when checked length at start"),
-                        }
-                    }
-                } else {
-                    Err(Error::RangeIndexError {
+                return match (normalize(e, len), self.0) {
+                    (Some(e), Value::Array(mut f)) if e < len => Ok(f.swap_remove(e).0),
+                    (Some(e), _) if e < len => Ok(Value::String(chars[e].to_string())),
+                    _ => Err(Error::IndexError {
                         index: e,
                         len,
                         lspan: self.1,
                         rspan: idx.1,
-                    })
-                }
+                    }),
+                };
             }
-            _ => Err(Error::TypeError {
-                expected: vec![ValueType::Num, ValueType::Range],
-                got: idx,
-                context: TypeErrorCtx::Index,
-            }),
+            Value::Range(e) => slice(e, len),
+            _ => {
+                return Err(Error::TypeError {
+                    expected: vec![ValueType::Int, ValueType::Range],
+                    got: idx,
+                    context: TypeErrorCtx::Index,
+                })
+            }
+        };
+
+        let picked = match picked {
+            Some(picked) => picked,
+            None => {
+                let Value::Range(index) = idx.0 else {
+                    unreachable!("Only ranges are sliced")
+                };
+
+                return Err(Error::RangeIndexError {
+                    index,
+                    len,
+                    lspan: self.1,
+                    rspan: idx.1,
+                });
+            }
+        };
+
+        match self.0 {
+            Value::Array(f) => Ok(Value::Array(
+                picked.into_iter().map(|i| f[i].clone()).collect(),
+            )),
+            _ => Ok(Value::String(
+                picked.into_iter().map(|i| chars[i]).collect(),
+            )),
         }
     }
 
     pub fn range(self, rhs: Self) -> Result<Value, Error> {
-        match self.0 {
-            Value::Num(e) => {
-                if e == e.trunc() {
-                    let e = e as isize;
-
-                    match rhs.0 {
-                        Value::Num(f) => {
-                            if f == f.trunc() {
-                                let f = f as isize;
-
-                                Ok(Value::Range(e..f))
-                            } else {
-                                Err(Error::TypeError {
-                                    expected: ValueType::Int.into(),
-                                    got: rhs,
-                                    context: TypeErrorCtx::Range,
-                                })
-                            }
-                        }
-                        _ => Err(Error::TypeError {
-                            expected: ValueType::Num.into(),
-                            got: rhs,
-                            context: TypeErrorCtx::Range,
-                        }),
-                    }
-                } else {
-                    Err(Error::TypeError {
-                        expected: ValueType::Int.into(),
-                        got: self,
-                        context: TypeErrorCtx::Range,
-                    })
-                }
+        Ok(Value::Range(IntRange::new(
+            bound(&self)?,
+            bound(&rhs)?,
+            false,
+        )))
+    }
+
+    pub fn irange(self, rhs: Self) -> Result<Value, Error> {
+        Ok(Value::Range(IntRange::new(
+            bound(&self)?,
+            bound(&rhs)?,
+            true,
+        )))
+    }
+
+    // `range by step`
+    pub fn step(self, rhs: Self) -> Result<Value, Error> {
+        let range = match self.0 {
+            Value::Range(range) => range,
+            _ => {
+                return Err(Error::TypeError {
+                    expected: ValueType::Range.into(),
+                    got: self,
+                    context: TypeErrorCtx::InfixOpLhs { op: InfixOp::By },
+                })
             }
+        };
+
+        match rhs.0 {
+            Value::Int(step) if step > 0 => Ok(Value::Range(IntRange {
+                step: step as u64,
+                ..range
+            })),
+            Value::Int(step) => Err(Error::StepError { step, span: rhs.1 }),
             _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
-                got: self,
-                context: TypeErrorCtx::Range,
+                expected: ValueType::Int.into(),
+                got: rhs,
+                context: TypeErrorCtx::Step,
             }),
         }
     }
+}
 
-    pub fn irange(self, rhs: Self) -> Result<Value, Error> {
+// ranges only go between whole numbers
+fn bound(value: &SpannedValue) -> Result<i64, Error> {
+    match value.0 {
+        Value::Int(e) => Ok(e),
+        _ => Err(Error::TypeError {
+            expected: ValueType::Int.into(),
+            got: value.clone(),
+            context: TypeErrorCtx::Range,
+        }),
+    }
+}
+
+impl Neg for SpannedValue {
+    type Output = Result<Value, Error>;
+
+    fn neg(self) -> Result<Value, Error> {
         match self.0 {
-            Value::Num(e) => {
-                if e == e.trunc() {
-                    let e = e as isize;
-
-                    match rhs.0 {
-                        Value::Num(f) => {
-                            if f == f.trunc() {
-                                let f = f as isize;
-
-                                Ok(Value::IRange(e..f))
-                            } else {
-                                Err(Error::TypeError {
-                                    expected: ValueType::Int.into(),
-                                    got: rhs,
-                                    context: TypeErrorCtx::Range,
-                                })
-                            }
-                        }
-                        _ => Err(Error::TypeError {
-                            expected: ValueType::Num.into(),
-                            got: rhs,
-                            context: TypeErrorCtx::Range,
-                        }),
-                    }
-                } else {
-                    Err(Error::TypeError {
-                        expected: ValueType::Int.into(),
-                        got: self,
-                        context: TypeErrorCtx::Range,
-                    })
-                }
-            }
+            Value::Int(e) => match e.checked_neg() {
+                Some(e) => Ok(Value::Int(e)),
+                None => Err(Error::OverflowError { span: self.1 }),
+            },
+            Value::Num(e) => Ok(Value::Num(-e)),
+            Value::Dice(e) => dice(e.negate(), &self.1),
             _ => Err(Error::TypeError {
-                expected: ValueType::Num.into(),
+                expected: vec![ValueType::Num, ValueType::Dice],
                 got: self,
-                context: TypeErrorCtx::Range,
+                context: TypeErrorCtx::Neg,
             }),
         }
     }
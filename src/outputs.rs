@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use crate::{
+    ast::Modifiers,
+    interpreter::VarStore,
+    value::{Value, ValueMap},
+};
+
+impl VarStore {
+    // the `pub` and `out` modifiers a variable was declared with
+    pub fn modifiers(&self, name: &str) -> Modifiers {
+        self.modifiers.get(name).copied().unwrap_or_default()
+    }
+
+    // the `#[..]` metadata a variable or block was declared with
+    pub fn annotations(&self, name: &str) -> Option<&HashMap<String, Value>> {
+        self.annotations.get(name)
+    }
+
+    // only the variables marked `out`, leaving out every intermediate value
+    pub fn outputs(&self) -> ValueMap {
+        let values = self
+            .values
+            .iter()
+            .filter(|(name, _)| self.modifiers(name).output)
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::Input(_, _, inner) => *inner.clone(),
+                    _ => value.clone(),
+                };
+
+                (name.clone(), value)
+            })
+            .collect();
+
+        ValueMap { values }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{interpreter::interpret, value::Value};
+
+    #[test]
+    fn outputs_only() {
+        let store = interpret(
+            "input base = 3;
+            helper = base * 2;
+            pub out total = helper + 1;
+            out pub input shown = 'nice';",
+        )
+        .unwrap();
+
+        let outputs = store.outputs().values;
+
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs.get("total").unwrap(), &Value::Int(7));
+        assert_eq!(
+            outputs.get("shown").unwrap(),
+            &Value::String("nice".to_owned())
+        );
+        assert!(store.modifiers("total").public);
+        assert!(!store.modifiers("helper").public);
+    }
+
+    #[test]
+    fn outputs_serialize() {
+        let store = interpret("helper = 2; out total = helper * 3;").unwrap();
+        let serialized = serde_json::to_string(&store.outputs()).unwrap();
+
+        assert_eq!(serialized, r#"{"total":6}"#);
+    }
+
+    #[test]
+    fn outputs_serialize_record() {
+        let store =
+            interpret("out item = { name: 'Rope', tags: ['climbing', 'utility'] };").unwrap();
+        let serialized = serde_json::to_string(&store.outputs()).unwrap();
+
+        assert_eq!(
+            serialized,
+            r#"{"item":{"name":"Rope","tags":["climbing","utility"]}}"#
+        );
+    }
+}
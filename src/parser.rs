@@ -1,250 +1,804 @@
-use chumsky::prelude::*;
+use chumsky::{prelude::*, Stream};
 
 use crate::{
-    ast::{Expr, InfixOp, Literal, Spanned, Token},
+    ast::{Expr, FormatPart, InfixOp, Literal, Modifiers, Span, Spanned, StringPart, Token},
+    dice::Dice,
+    format::FormatSpec,
+    lexer::lexer,
     value::ValueType,
 };
 
 pub fn parse() -> impl Parser<Token, Vec<Spanned>, Error = Simple<Token>> + Clone {
+    statement().repeated().at_least(1).then_ignore(end())
+}
+
+// a single expression and nothing else, like a value given for an input
+pub fn parse_expr() -> impl Parser<Token, Spanned, Error = Simple<Token>> + Clone {
+    expr().then_ignore(end())
+}
+
+// a declaration, a block of declarations, or a bare expression
+fn statement() -> impl Parser<Token, Spanned, Error = Simple<Token>> + Clone {
+    let ident = select! { Token::Ident(ident) => ident.clone() }.labelled("identifier");
+    let raw_expr = expr();
+
+    recursive(|statement| {
+        // checked whenever the value is set, with `@` standing in for it
+        let constraint = just(Token::Where)
+            .ignore_then(raw_expr.clone())
+            .labelled("constraint")
+            .or_not();
+
+        let annotation = ident
+            .then(
+                select! { Token::Num(e) => e }
+                    .try_map(|e, span| number(&e, span))
+                    .or(select! {
+                        Token::String(e) => Literal::String(e),
+                        Token::Bool(e) => Literal::Bool(e),
+                    })
+                    .labelled("annotation value")
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .labelled("annotation");
+
+        let annotations = just(Token::Ctrl('#'))
+            .ignore_then(
+                annotation
+                    .separated_by(just(Token::Ctrl(',')))
+                    .allow_trailing()
+                    .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']'))),
+            )
+            .repeated()
+            .flatten();
+
+        // `pub` and `out`, in either order
+        let modifiers = just(Token::Pub)
+            .or(just(Token::Out))
+            .repeated()
+            .map(|found| Modifiers {
+                public: found.contains(&Token::Pub),
+                output: found.contains(&Token::Out),
+            });
+
+        let assign = annotations
+            .clone()
+            .then(modifiers.clone())
+            .then(
+                ident.chain(
+                    just(Token::Op("=".to_owned()))
+                        .ignore_then(ident)
+                        .labelled("assign chain")
+                        .then_ignore(just(Token::Op("=".to_owned())).rewind())
+                        .repeated(),
+                ),
+            )
+            .then(
+                just(Token::Op("=".to_owned()))
+                    .ignore_then(raw_expr.clone())
+                    .labelled("final assign"),
+            )
+            .then(constraint.clone())
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(
+                |((((annotations, modifiers), names), val), constraint)| Expr::Assign {
+                    names,
+                    value: Box::new(val),
+                    constraint: constraint.map(Box::new),
+                    modifiers,
+                    annotations,
+                },
+            )
+            .map_with_span(Spanned)
+            .boxed();
+
+        let input = annotations
+            .clone()
+            .then(modifiers.clone())
+            .then_ignore(just(Token::Input))
+            .then(ident)
+            .then(just(Token::Ctrl(':')).ignore_then(kind()).or_not())
+            .then(
+                just(Token::Op("=".to_owned()))
+                    .ignore_then(raw_expr.clone())
+                    .labelled("default")
+                    .or_not(),
+            )
+            .then(constraint)
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(
+                |(((((annotations, modifiers), name), kind), default), constraint)| Expr::Input {
+                    name,
+                    kind: kind.unwrap_or(ValueType::Any),
+                    default: default.map(Box::new),
+                    constraint: constraint.map(Box::new),
+                    modifiers,
+                    annotations,
+                },
+            )
+            .map_with_span(Spanned);
+
+        // `fn name(a, b) = body;` is sugar for assigning a lambda that can call itself by name
+        let function = annotations
+            .clone()
+            .then(modifiers.clone())
+            .then_ignore(just(Token::Fn))
+            .then(ident)
+            .then(
+                ident
+                    .separated_by(just(Token::Ctrl(',')))
+                    .allow_trailing()
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .then_ignore(just(Token::Op("=".to_owned())))
+            .then(raw_expr.clone())
+            .then_ignore(just(Token::Ctrl(';')))
+            .map_with_span(|((((annotations, modifiers), name), params), body), span| {
+                let lambda = Expr::Lambda {
+                    name: Some(name.clone()),
+                    params,
+                    body: Box::new(body),
+                };
+
+                let assign = Expr::Assign {
+                    names: vec![name],
+                    value: Box::new(Spanned(lambda, span.clone())),
+                    constraint: None,
+                    modifiers,
+                    annotations,
+                };
+
+                Spanned(assign, span)
+            });
+
+        // `use "rules/core.plm" as core;`
+        let import = just(Token::Use)
+            .ignore_then(select! { Token::String(path) => path }.labelled("path"))
+            .then_ignore(just(Token::As))
+            .then(ident)
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(|(path, alias)| Expr::Use { path, alias })
+            .map_with_span(Spanned);
+
+        // namespace of declarations, accessed from outside with `name.member`
+        let block = annotations
+            .clone()
+            .then(ident)
+            .then(
+                statement
+                    .clone()
+                    .repeated()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+            )
+            .map(|((annotations, name), items)| Expr::Block {
+                name,
+                items,
+                annotations,
+            })
+            .map_with_span(Spanned);
+
+        let template = just(Token::Macro("common".to_owned()))
+            .ignore_then(
+                statement
+                    .repeated()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
+            )
+            .map(Expr::Template)
+            .map_with_span(Spanned);
+
+        let member = annotations
+            .then(modifiers)
+            .then(ident)
+            .then_ignore(just(Token::Ctrl(';')))
+            .map(|((annotations, modifiers), name)| Expr::Member {
+                name,
+                modifiers,
+                annotations,
+            })
+            .map_with_span(Spanned);
+
+        import
+            .or(input)
+            .or(function)
+            .or(assign)
+            .or(block)
+            .or(template)
+            .or(member)
+            .or(raw_expr)
+    })
+}
+
+// a type name, or `[kind]` for arrays of it
+fn kind() -> impl Parser<Token, ValueType, Error = Simple<Token>> + Clone {
+    recursive(|kind| {
+        let name = select! { Token::Type(e) => e }.try_map(|name, span| {
+            ValueType::named(&name)
+                .ok_or_else(|| Simple::custom(span, format!("`{}` isn't a type", name)))
+        });
+
+        name.or(kind
+            .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+            .map(|inner| ValueType::ArrayOf(Box::new(inner))))
+    })
+    .labelled("type")
+}
+
+fn expr() -> impl Parser<Token, Spanned, Error = Simple<Token>> + Clone {
     recursive(|expr| {
         let ident = select! { Token::Ident(ident) => ident.clone() }.labelled("identifier");
 
-        let raw_expr = {
-            let val = select! {
-                Token::Num(e) => Expr::from(e.parse::<f64>().unwrap()),
+        let val = select! { Token::Num(e) => e }
+            .try_map(|e, span| number(&e, span).map(Expr::Literal))
+            .or(select! {
                 Token::String(e) => Expr::from(e),
                 Token::Bool(e) => Expr::from(e),
-            }
+                Token::Null => Expr::Literal(Literal::Null),
+            })
             .labelled("value")
             .map_with_span(Spanned);
 
-            // Array items
-            let items = expr
-                .clone()
-                .separated_by(just(Token::Ctrl(',')))
-                .allow_trailing();
+        // `"{name} ({modifier:+})"`
+        let format = select! { Token::Format(parts) => parts }
+            .try_map(|parts, span: Span| {
+                parts
+                    .into_iter()
+                    .map(|part| match part {
+                        StringPart::Text(text) => Ok(FormatPart::Text(text)),
+                        StringPart::Interpolation {
+                            source,
+                            spec,
+                            offset,
+                        } => {
+                            let start = span.start + offset;
+                            let value = interpolation(&source, start)?;
+                            let spec = match spec {
+                                Some(spec) => spec.parse::<FormatSpec>().map_err(|msg| {
+                                    Simple::custom(start..start + source.chars().count(), msg)
+                                })?,
+                                None => FormatSpec::default(),
+                            };
+
+                            Ok(FormatPart::Value(value, spec))
+                        }
+                    })
+                    .collect::<Result<Vec<FormatPart>, Simple<Token>>>()
+            })
+            .map(Expr::Format)
+            .labelled("interpolated string")
+            .map_with_span(Spanned);
 
-            let array = items
-                .clone()
-                .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
-                .map(Literal::Array)
-                .map(Expr::Literal)
-                .map_with_span(Spanned);
+        let dice = select! { Token::Dice(e) => e }
+            .try_map(|e, span| e.parse::<Dice>().map_err(|msg| Simple::custom(span, msg)))
+            .map(|e| Expr::Literal(Literal::Dice(e)))
+            .labelled("dice")
+            .map_with_span(Spanned);
 
-            let single_expr = expr
-                .clone()
-                .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')));
-
-            let atom_ident = ident.map(Expr::Ident).map_with_span(Spanned);
-            let atom = choice((val, atom_ident, array, single_expr))
-                .recover_with(nested_delimiters(
-                    Token::Ctrl('('),
-                    Token::Ctrl(')'),
-                    [
-                        (Token::Ctrl('['), Token::Ctrl(']')),
-                        (Token::Ctrl('{'), Token::Ctrl('}')),
-                    ],
-                    |span| Spanned(Expr::Error, span),
-                ))
-                // Attempt to recover anything that looks like a list but contains errors
-                .recover_with(nested_delimiters(
-                    Token::Ctrl('['),
-                    Token::Ctrl(']'),
-                    [
-                        (Token::Ctrl('('), Token::Ctrl(')')),
-                        (Token::Ctrl('{'), Token::Ctrl('}')),
-                    ],
-                    |span| Spanned(Expr::Error, span),
-                ))
-                .boxed();
-
-            let index = atom
-                .then(
-                    expr.clone()
-                        .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
-                        .repeated()
-                        .labelled("index"),
-                )
-                .foldl(|lhs, rhs: Spanned| {
-                    let span = lhs.1.start..rhs.1.end;
+        // Array items
+        let items = expr
+            .clone()
+            .separated_by(just(Token::Ctrl(',')))
+            .allow_trailing();
 
-                    Spanned(Expr::Index(Box::new(lhs), Box::new(rhs)), span)
-                });
+        // `[x * 2 for x in items if x > 0]`
+        let comprehension = expr
+            .clone()
+            .then_ignore(just(Token::For))
+            .then(ident)
+            .then_ignore(just(Token::Op("in".to_owned())))
+            .then(expr.clone())
+            .then(just(Token::If).ignore_then(expr.clone()).or_not())
+            .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+            .map(|(((item, var), source), condition)| Expr::Comprehension {
+                item: Box::new(item),
+                var,
+                source: Box::new(source),
+                condition: condition.map(Box::new),
+            })
+            .labelled("comprehension")
+            .map_with_span(Spanned);
 
-            let op = just(Token::Op("..=".to_owned())).to(InfixOp::IRange);
-            let irange = index
-                .clone()
-                .then(op.then(index).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
-                .boxed();
+        let array = items
+            .clone()
+            .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+            .map(Literal::Array)
+            .map(Expr::Literal)
+            .map_with_span(Spanned);
 
-            let op = just(Token::Op("..".to_owned())).to(InfixOp::Range);
-            let range = irange
-                .clone()
-                .then(op.then(irange).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+        // `{ name: "Rope", weight: 10 }`
+        let record = ident
+            .then_ignore(just(Token::Ctrl(':')))
+            .then(expr.clone())
+            .separated_by(just(Token::Ctrl(',')))
+            .allow_trailing()
+            .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+            .try_map(|fields: Vec<(String, Spanned)>, span| {
+                for (idx, (name, _)) in fields.iter().enumerate() {
+                    if fields[..idx].iter().any(|(other, _)| other == name) {
+                        return Err(Simple::custom(span, format!("`{}` is given twice", name)));
+                    }
+                }
 
-            let op = just(Token::Op("**".to_owned())).to(InfixOp::Pow);
-            let pow = range
-                .clone()
-                .then(op.then(range).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
-                .boxed();
-
-            let op = choice((
-                just(Token::Op("*".to_owned()))
-                    .labelled("multiply")
-                    .to(InfixOp::Mul),
-                just(Token::Op("/".to_owned()))
-                    .labelled("divide")
-                    .to(InfixOp::Div),
-                just(Token::Op("%".to_owned()))
-                    .labelled("modulus")
-                    .to(InfixOp::Mod),
-            ));
-            let product = pow
-                .clone()
-                .then(op.then(pow).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
-
-            let op = choice((
-                just(Token::Op("+".to_owned()))
-                    .labelled("add")
-                    .to(InfixOp::Add),
-                just(Token::Op("-".to_owned()))
-                    .labelled("subtract")
-                    .to(InfixOp::Sub),
-            ));
-            let sum = product
-                .clone()
-                .then(op.then(product).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
-                .boxed();
-
-            let op = choice((
-                just(Token::Op("==".to_owned()))
-                    .labelled("equals")
-                    .to(InfixOp::Equals),
-                just(Token::Op("<=".to_owned()))
-                    .labelled("less than or equal")
-                    .to(InfixOp::Lte),
-                just(Token::Op(">=".to_owned()))
-                    .labelled("greater than or equal")
-                    .to(InfixOp::Gte),
-                just(Token::Op("<".to_owned()))
-                    .labelled("less than")
-                    .to(InfixOp::Lt),
-                just(Token::Op(">".to_owned()))
-                    .labelled("greater than")
-                    .to(InfixOp::Gt),
-            ));
-            let compare = sum
-                .clone()
-                .then(op.then(sum).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+                Ok(fields)
+            })
+            .map(Literal::Record)
+            .map(Expr::Literal)
+            .labelled("record")
+            .map_with_span(Spanned);
 
-            let op = just(Token::Op("in".to_owned()))
-                .labelled("in")
-                .to(InfixOp::In);
-            let contains = compare
-                .clone()
-                .then(op.then(compare).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
-                .boxed();
-
-            let op = just(Token::Op("&&".to_owned()))
-            .labelled("and")
-            .to(InfixOp::And);
-            let and = contains
-                .clone()
-                .then(op.then(contains).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+        let single_expr = expr
+            .clone()
+            .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')));
 
-            let op = just(Token::Op("||".to_owned()))
-            .labelled("or")
-            .to(InfixOp::Or);
-            let or = and
-                .clone()
-                .then(op.then(and).repeated())
-                .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+        // the value being checked in a `where` constraint
+        let current = just(Token::Op("@".to_owned()))
+            .to(Expr::Ident("@".to_owned()))
+            .labelled("constrained value")
+            .map_with_span(Spanned);
 
-            let conditional = recursive(|cond| {
-                let block = expr
+        let atom_ident = ident.map(Expr::Ident).map_with_span(Spanned);
+        let macro_call = select! { Token::Macro(name) => name }
+            .then(
+                items
                     .clone()
-                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')));
-
-                just(Token::If)
-                    .ignore_then(expr.clone())
-                    .then(block.clone())
-                    .then_ignore(just(Token::Else))
-                    .then(block.or(cond))
-                    .map(|((condition, inner), other)| {
-                        let span = condition.1.start..other.1.end;
-
-                        Spanned(
-                            Expr::Conditional {
-                                condition: Box::new(condition),
-                                inner: Box::new(inner),
-                                other: Box::new(other),
-                            },
-                            span,
-                        )
-                    })
-            });
+                    .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')'))),
+            )
+            .map(|(name, args)| Expr::Macro(name, args))
+            .labelled("macro")
+            .map_with_span(Spanned);
 
-            conditional.or(or)
+        // `|a, b| a + b`, where `||` starts a lambda without parameters
+        let lambda = ident
+            .separated_by(just(Token::Ctrl(',')))
+            .allow_trailing()
+            .delimited_by(
+                just(Token::Op("|".to_owned())),
+                just(Token::Op("|".to_owned())),
+            )
+            .or(just(Token::Op("||".to_owned())).to(Vec::new()))
+            .then(expr.clone())
+            .map(|(params, body)| Expr::Lambda {
+                name: None,
+                params,
+                body: Box::new(body),
+            })
+            .labelled("lambda")
+            .map_with_span(Spanned);
+
+        // `let x = a + b; let y = x * 2; y + 1`, each binding visible to everything after it
+        let binding = just(Token::Let)
+            .map_with_span(|_, span: Span| span.start)
+            .then(ident)
+            .then_ignore(just(Token::Op("=".to_owned())))
+            .then(expr.clone())
+            .then_ignore(just(Token::Ctrl(';')));
+
+        let scoped = |bindings: Vec<((usize, String), Spanned)>, body: Spanned| {
+            bindings
+                .into_iter()
+                .rev()
+                .fold(body, |body, ((start, name), value)| {
+                    let span = start..body.1.end;
+
+                    Spanned(
+                        Expr::Let {
+                            name,
+                            value: Box::new(value),
+                            body: Box::new(body),
+                        },
+                        span,
+                    )
+                })
         };
 
-        let assign = ident
+        // the body of a conditional, which can have bindings of its own
+        let block = binding
             .clone()
-            .chain(
-                just(Token::Op("=".to_owned()))
-                    .ignore_then(ident)
-                    .labelled("assign chain")
-                    .then_ignore(just(Token::Op("=".to_owned())).rewind())
-                    .repeated(),
-            )
+            .repeated()
+            .then(expr.clone())
+            .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+            .map(move |(bindings, body)| scoped(bindings, body));
+
+        let let_block = binding
+            .repeated()
+            .at_least(1)
+            .then(expr.clone())
+            .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}')))
+            .map(move |(bindings, body)| scoped(bindings, body))
+            .labelled("let")
+            .map_with_span(|Spanned(expr, _), span| Spanned(expr, span));
+
+        // `match x { 1..=4 => 2, 5 | 6 => 3, _ => 4 }`
+        let arm = expr
+            .clone()
+            .separated_by(just(Token::Op("|".to_owned())))
+            .at_least(1)
+            .then_ignore(just(Token::Op("=>".to_owned())))
+            .then(expr.clone());
+
+        let match_expr = just(Token::Match)
+            .ignore_then(expr.clone())
             .then(
-                just(Token::Op("=".to_owned()))
-                    .ignore_then(raw_expr.clone())
-                    .labelled("final assign"),
+                arm.separated_by(just(Token::Ctrl(',')))
+                    .allow_trailing()
+                    .delimited_by(just(Token::Ctrl('{')), just(Token::Ctrl('}'))),
             )
-            .then_ignore(just(Token::Ctrl(';')))
-            .map(|(names, val)| Expr::Assign {
-                names,
-                value: Box::new(val),
+            .map(|(value, arms)| Expr::Match {
+                value: Box::new(value),
+                arms,
             })
-            .map_with_span(Spanned)
+            .labelled("match")
+            .map_with_span(Spanned);
+
+        let atom = choice((
+            val,
+            format,
+            dice,
+            current,
+            macro_call,
+            atom_ident,
+            comprehension,
+            array,
+            record,
+            lambda,
+            match_expr,
+            let_block,
+            single_expr,
+        ))
+        .recover_with(nested_delimiters(
+            Token::Ctrl('('),
+            Token::Ctrl(')'),
+            [
+                (Token::Ctrl('['), Token::Ctrl(']')),
+                (Token::Ctrl('{'), Token::Ctrl('}')),
+            ],
+            |span| Spanned(Expr::Error, span),
+        ))
+        // Attempt to recover anything that looks like a list but contains errors
+        .recover_with(nested_delimiters(
+            Token::Ctrl('['),
+            Token::Ctrl(']'),
+            [
+                (Token::Ctrl('('), Token::Ctrl(')')),
+                (Token::Ctrl('{'), Token::Ctrl('}')),
+            ],
+            |span| Spanned(Expr::Error, span),
+        ))
+        .boxed();
+
+        let postfix = choice((
+            expr.clone()
+                .delimited_by(just(Token::Ctrl('[')), just(Token::Ctrl(']')))
+                .labelled("index")
+                .map(Postfix::Index),
+            just(Token::Op(".".to_owned()))
+                .ignore_then(ident.map(Expr::Ident).map_with_span(Spanned))
+                .labelled("access")
+                .map(Postfix::Access),
+            items
+                .clone()
+                .delimited_by(just(Token::Ctrl('(')), just(Token::Ctrl(')')))
+                .labelled("call")
+                .map_with_span(Postfix::Call),
+        ));
+
+        let index = atom.then(postfix.repeated()).foldl(|lhs, rhs| match rhs {
+            Postfix::Index(rhs) => {
+                let span = lhs.1.start..rhs.1.end;
+
+                Spanned(Expr::Index(Box::new(lhs), Box::new(rhs)), span)
+            }
+            Postfix::Access(rhs) => {
+                let span = lhs.1.start..rhs.1.end;
+
+                Spanned(Expr::Access(Box::new(lhs), Box::new(rhs)), span)
+            }
+            Postfix::Call(args, args_span) => {
+                let span = lhs.1.start..args_span.end;
+
+                Spanned(Expr::Call(Box::new(lhs), args), span)
+            }
+        });
+
+        // `-` and `!` bind tighter than any infix operator but `**`, so `-2 ** 2` is `-(2 ** 2)`
+        let op = just(Token::Op("-".to_owned()))
+            .labelled("negate")
+            .to(Prefix::Neg)
+            .or(just(Token::Op("!".to_owned()))
+                .labelled("not")
+                .to(Prefix::Not))
+            .map_with_span(|op, span: Span| (op, span));
+
+        // the exponent can still be negated, like in `2 ** -1`
+        let exponent = op
+            .clone()
+            .repeated()
+            .then(index.clone())
+            .foldr(|(op, span), rhs| prefix(op, span, rhs));
+
+        let pow_op = just(Token::Op("**".to_owned())).to(InfixOp::Pow);
+        let pow = index
+            .then(pow_op.then(exponent).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+
+        let unary = op
+            .repeated()
+            .then(pow)
+            .foldr(|(op, span), rhs| prefix(op, span, rhs))
             .boxed();
 
-        let input = just(Token::Input)
-            .ignore_then(ident.clone())
-            .then(
-                just(Token::Ctrl(':'))
-                    .ignore_then(select! {
-                        Token::Type(e) => match e.as_str() {
-                            "Num" => ValueType::Num,
-                            "Int" => ValueType::Int,
-                            "String" => ValueType::String,
-                            "Bool" => ValueType::Bool,
-                            "Array" => ValueType::Array,
-                            _ => unreachable!("Type token won't be made for other strings"),
+        let op = just(Token::Op("..=".to_owned())).to(InfixOp::IRange);
+        let irange = unary
+            .clone()
+            .then(op.then(unary).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
+            .boxed();
+
+        let op = just(Token::Op("..".to_owned())).to(InfixOp::Range);
+        let range = irange
+            .clone()
+            .then(op.then(irange).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+
+        let op = just(Token::Op("by".to_owned())).to(InfixOp::By);
+        let step = range
+            .clone()
+            .then(op.then(range).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
+            .boxed();
+
+        let op = choice((
+            just(Token::Op("*".to_owned()))
+                .labelled("multiply")
+                .to(InfixOp::Mul),
+            just(Token::Op("/".to_owned()))
+                .labelled("divide")
+                .to(InfixOp::Div),
+            just(Token::Op("%".to_owned()))
+                .labelled("modulus")
+                .to(InfixOp::Mod),
+            just(Token::Op("div".to_owned()))
+                .labelled("divide and round down")
+                .to(InfixOp::FloorDiv),
+            just(Token::Op("rdiv".to_owned()))
+                .labelled("divide and round")
+                .to(InfixOp::RoundDiv),
+            just(Token::Op("ediv".to_owned()))
+                .labelled("divide and round to even")
+                .to(InfixOp::EvenDiv),
+        ));
+        let product = step
+            .clone()
+            .then(op.then(step).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+
+        let op = choice((
+            just(Token::Op("+".to_owned()))
+                .labelled("add")
+                .to(InfixOp::Add),
+            just(Token::Op("-".to_owned()))
+                .labelled("subtract")
+                .to(InfixOp::Sub),
+        ));
+        let sum = product
+            .clone()
+            .then(op.then(product).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
+            .boxed();
+
+        let op = choice((
+            just(Token::Op("==".to_owned()))
+                .labelled("equals")
+                .to(InfixOp::Equals),
+            just(Token::Op("!=".to_owned()))
+                .labelled("not equals")
+                .to(InfixOp::NotEquals),
+            just(Token::Op("<=".to_owned()))
+                .labelled("less than or equal")
+                .to(InfixOp::Lte),
+            just(Token::Op(">=".to_owned()))
+                .labelled("greater than or equal")
+                .to(InfixOp::Gte),
+            just(Token::Op("<".to_owned()))
+                .labelled("less than")
+                .to(InfixOp::Lt),
+            just(Token::Op(">".to_owned()))
+                .labelled("greater than")
+                .to(InfixOp::Gt),
+        ));
+        // a single comparison stays a plain operator, longer ones are chained
+        let compare = sum
+            .clone()
+            .then(op.then(sum).repeated())
+            .map(|(first, mut rest)| match rest.len() {
+                0 => first,
+                1 => {
+                    let (op, rhs) = rest.remove(0);
+
+                    spannify(first, op, rhs)
+                }
+                _ => {
+                    let span = first.1.start..rest[rest.len() - 1].1 .1.end;
+
+                    Spanned(
+                        Expr::Compare {
+                            first: Box::new(first),
+                            rest,
                         },
-                    })
-                    .or_not(),
-            )
-            .then_ignore(just(Token::Ctrl(';')))
-            .map(|(name, kind)| match kind {
-                Some(kind) => Expr::Input(name, kind),
-                None => Expr::Input(name, ValueType::Any),
+                        span,
+                    )
+                }
             })
-            .map_with_span(Spanned);
+            .boxed();
+
+        let op = just(Token::Op("in".to_owned()))
+            .labelled("in")
+            .to(InfixOp::In);
+        let contains = compare
+            .clone()
+            .then(op.then(compare.clone()).repeated())
+            .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
+            .boxed();
+
+        let or = logic(contains);
+
+        let conditional = recursive(|cond| {
+            just(Token::If)
+                .ignore_then(expr.clone())
+                .then(block.clone())
+                .then_ignore(just(Token::Else))
+                .then(block.or(cond))
+                .map(|((condition, inner), other)| {
+                    let span = condition.1.start..other.1.end;
+
+                    Spanned(
+                        Expr::Conditional {
+                            condition: Box::new(condition),
+                            inner: Box::new(inner),
+                            other: Box::new(other),
+                        },
+                        span,
+                    )
+                })
+        });
+
+        // `let x = a + b in x * 2`, where the body goes as far right as it can
+        // the value can't use `in` without parens, since that `in` ends it
+        let let_in = just(Token::Let)
+            .map_with_span(|_, span: Span| span.start)
+            .then(ident)
+            .then_ignore(just(Token::Op("=".to_owned())))
+            .then(conditional.clone().or(logic(compare)))
+            .then_ignore(just(Token::Op("in".to_owned())))
+            .then(expr.clone())
+            .map(|(((start, name), value), body)| {
+                let span = start..body.1.end;
+
+                Spanned(
+                    Expr::Let {
+                        name,
+                        value: Box::new(value),
+                        body: Box::new(body),
+                    },
+                    span,
+                )
+            })
+            .labelled("let");
+
+        conditional.or(let_in).or(or)
+    })
+}
+
+// `not`, `and` and `or`, over everything that binds tighter than them
+fn logic<'a>(
+    operand: impl Parser<Token, Spanned, Error = Simple<Token>> + Clone + 'a,
+) -> impl Parser<Token, Spanned, Error = Simple<Token>> + Clone + 'a {
+    // `not` reads as a word, so it binds looser than comparisons like it does in English
+    let op = just(Token::Op("not".to_owned()))
+        .labelled("not")
+        .to(Prefix::Not)
+        .map_with_span(|op, span: Span| (op, span));
+    let not = op
+        .repeated()
+        .then(operand)
+        .foldr(|(op, span), rhs| prefix(op, span, rhs))
+        .boxed();
+
+    let op = just(Token::Op("&&".to_owned()))
+        .or(just(Token::Op("and".to_owned())))
+        .labelled("and")
+        .to(InfixOp::And);
+    let and = not
+        .clone()
+        .then(op.then(not).repeated())
+        .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs));
+
+    let op = just(Token::Op("||".to_owned()))
+        .or(just(Token::Op("or".to_owned())))
+        .labelled("or")
+        .to(InfixOp::Or);
+
+    and.clone()
+        .then(op.then(and).repeated())
+        .foldl(|lhs, (op, rhs)| spannify(lhs, op, rhs))
+}
+
+// operations that trail an atom, like indexing
+#[derive(Clone)]
+enum Postfix {
+    Index(Spanned),
+    Access(Spanned),
+    Call(Vec<Spanned>, Span),
+}
+
+#[derive(Clone)]
+enum Prefix {
+    Neg,
+    Not,
+}
+
+// the expression inside `{..}` in a string, lexed and parsed on its own
+// `start` is where it is in the whole source, so its spans still line up
+fn interpolation(source: &str, start: usize) -> Result<Spanned, Simple<Token>> {
+    let len = source.chars().count();
+
+    if source.trim().is_empty() {
+        return Err(Simple::custom(
+            start..start + len,
+            "Interpolations can't be empty",
+        ));
+    }
+
+    let tokens = lexer().parse(source).map_err(|errs| {
+        let span = errs[0].span();
+
+        Simple::custom(span.start + start..span.end + start, errs[0].to_string())
+    })?;
+
+    let tokens = tokens
+        .into_iter()
+        .map(|(token, span)| (token, span.start + start..span.end + start));
+    let eoi = start + len;
 
-        input.or(assign).or(raw_expr)
+    expr()
+        .then_ignore(end())
+        .parse(Stream::from_iter(eoi..eoi + 1, tokens))
+        .map_err(|mut errs| errs.remove(0))
+}
+
+// whole numbers are ints, and it's an error for one to be too big for an `Int`
+// anything with a fraction or an exponent is a `Num`
+fn number(e: &str, span: Span) -> Result<Literal, Simple<Token>> {
+    if e.contains(['.', 'e', 'E']) {
+        return Ok(Literal::Num(e.parse::<f64>().unwrap()));
+    }
+
+    e.parse::<i64>().map(Literal::Int).map_err(|_| {
+        Simple::custom(
+            span,
+            format!(
+                "`{}` is too big to fit in an Int, write `{}.0` for a Num",
+                e, e
+            ),
+        )
     })
-    .repeated()
-    .at_least(1)
-    .then_ignore(end())
+}
+
+fn prefix(op: Prefix, op_span: Span, rhs: Spanned) -> Spanned {
+    let span = op_span.start..rhs.1.end;
+
+    let expr = match (op, rhs) {
+        // negative number literals stay literals
+        (Prefix::Neg, Spanned(Expr::Literal(Literal::Int(e)), _)) => {
+            Expr::Literal(Literal::Int(-e))
+        }
+        (Prefix::Neg, Spanned(Expr::Literal(Literal::Num(e)), _)) => {
+            Expr::Literal(Literal::Num(-e))
+        }
+        (Prefix::Neg, rhs) => Expr::Neg(Box::new(rhs)),
+        (Prefix::Not, rhs) => Expr::Not(Box::new(rhs)),
+    };
+
+    Spanned(expr, span)
 }
 
 fn spannify(lhs: Spanned, op: InfixOp, rhs: Spanned) -> Spanned {
@@ -258,7 +812,8 @@ mod tests {
     use chumsky::{Parser, Stream};
 
     use crate::{
-        ast::{Expr, InfixOp, Literal, Spanned},
+        ast::{Expr, FormatPart, InfixOp, Literal, Modifiers, Spanned},
+        format::FormatSpec,
         lexer::lexer,
         parser,
         value::ValueType,
@@ -276,7 +831,7 @@ mod tests {
     #[test]
     fn parse_neg() {
         let parsed = parse("-23");
-        assert_eq!(parsed[0], Spanned::from(-23.0));
+        assert_eq!(parsed[0], Spanned::from(-23));
     }
 
     #[test]
@@ -293,6 +848,26 @@ mod tests {
         assert_eq!(parsed[0], Spanned::from(182.0));
     }
 
+    #[test]
+    fn parse_big_int() {
+        assert_eq!(
+            parse("9223372036854775807")[0],
+            Spanned::from(9223372036854775807)
+        );
+        assert_eq!(parse("99999999999999999999.0")[0], Spanned::from(1e20));
+        assert_eq!(parse("1e20")[0], Spanned::from(1e20));
+
+        let source = "99999999999999999999";
+        let lexed = lexer().parse(source).unwrap();
+
+        assert!(parser::parse()
+            .parse(Stream::from_iter(
+                source.len()..source.len() + 1,
+                lexed.into_iter()
+            ))
+            .is_err());
+    }
+
     #[test]
     fn parse_num_array() {
         let parsed = parse("[1, 3.73, 2, 5.98e-2, 4]");
@@ -300,11 +875,11 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::Literal(Literal::Array(vec![
-                Spanned::from(1.0),
+                Spanned::from(1),
                 Spanned::from(3.73),
-                Spanned::from(2.0),
+                Spanned::from(2),
                 Spanned::from(0.0598),
-                Spanned::from(4.0)
+                Spanned::from(4)
             ]))
         );
     }
@@ -316,9 +891,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::Literal(Literal::Array(vec![
-                Spanned::from(1.0),
+                Spanned::from(1),
                 Spanned::from(true),
-                Spanned::from(2.0),
+                Spanned::from(2),
                 Spanned::from(false),
                 Spanned::from("nice"),
                 Spanned::from(935328.478)
@@ -332,13 +907,32 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(3.0)),
+                Box::new(Spanned::from(3)),
                 InfixOp::Mul,
-                Box::new(Spanned::from(7.0))
+                Box::new(Spanned::from(7))
             )
         );
     }
 
+    #[test]
+    fn parse_rounded_div() {
+        let parsed = parse("score - 10 div 2");
+
+        assert_eq!(
+            parsed[0],
+            Expr::InfixOp(
+                Box::new(Spanned::from(Expr::Ident("score".to_owned()))),
+                InfixOp::Sub,
+                Box::new(Spanned::from(Expr::InfixOp(
+                    Box::new(Spanned::from(10)),
+                    InfixOp::FloorDiv,
+                    Box::new(Spanned::from(2))
+                )))
+            )
+        );
+        assert_eq!(String::from(&parsed[0]), "score - (10 div 2)");
+    }
+
     #[test]
     fn parse_add() {
         let parsed = parse("10 + 83");
@@ -346,9 +940,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Add,
-                Box::new(Spanned::from(83.0))
+                Box::new(Spanned::from(83))
             )
         );
     }
@@ -360,19 +954,19 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Add,
                 Box::new(Spanned::from(Expr::InfixOp(
                     Box::new(Spanned::from(Expr::InfixOp(
-                        Box::new(Spanned::from(30.0)),
+                        Box::new(Spanned::from(30)),
                         InfixOp::Sub,
-                        Box::new(Spanned::from(5.0))
+                        Box::new(Spanned::from(5))
                     ))),
                     InfixOp::Mul,
                     Box::new(Spanned::from(Expr::InfixOp(
-                        Box::new(Spanned::from(3.0)),
+                        Box::new(Spanned::from(3)),
                         InfixOp::Pow,
-                        Box::new(Spanned::from(2.0))
+                        Box::new(Spanned::from(2))
                     )))
                 )))
             )
@@ -387,7 +981,10 @@ mod tests {
             parsed[0],
             Expr::Assign {
                 names: vec!["nice".to_owned()],
-                value: Box::new(Spanned::from(12.0))
+                value: Box::new(Spanned::from(12)),
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
             }
         )
     }
@@ -400,12 +997,12 @@ mod tests {
             parsed[0],
             Expr::Index(
                 Box::new(Spanned::from(vec![
-                    Spanned::from(1.0),
-                    Spanned::from(2.0),
-                    Spanned::from(3.0),
-                    Spanned::from(4.0)
+                    Spanned::from(1),
+                    Spanned::from(2),
+                    Spanned::from(3),
+                    Spanned::from(4)
                 ])),
-                Box::new(Spanned::from(3.0))
+                Box::new(Spanned::from(3))
             )
         )
     }
@@ -424,7 +1021,7 @@ mod tests {
             parsed[0],
             Expr::Conditional {
                 condition: Box::new(Spanned::from(Expr::Ident("cool".to_owned()))),
-                inner: Box::new(Spanned::from(36.0)),
+                inner: Box::new(Spanned::from(36)),
                 other: Box::new(Spanned::from(Expr::Ident("nice".to_owned())))
             }
         )
@@ -489,19 +1086,28 @@ mod tests {
             vec![
                 Expr::Assign {
                     names: vec!["cool".to_owned()],
-                    value: Box::new(Spanned::from(3.0))
+                    value: Box::new(Spanned::from(3)),
+                    constraint: None,
+                    modifiers: Modifiers::default(),
+                    annotations: Vec::new()
                 },
                 Expr::Assign {
                     names: vec!["nice".to_owned()],
                     value: Box::new(Spanned::from(Expr::InfixOp(
                         Box::new(Spanned::from(Expr::Ident("cool".to_owned()))),
                         InfixOp::Add,
-                        Box::new(Spanned::from(7.0))
-                    )))
+                        Box::new(Spanned::from(7))
+                    ))),
+                    constraint: None,
+                    modifiers: Modifiers::default(),
+                    annotations: Vec::new()
                 },
                 Expr::Assign {
                     names: vec!["sick".to_owned()],
                     value: Box::new(Spanned::from(false)),
+                    constraint: None,
+                    modifiers: Modifiers::default(),
+                    annotations: Vec::new()
                 }
             ]
         )
@@ -523,9 +1129,12 @@ mod tests {
                 names: vec!["cool".to_owned()],
                 value: Box::new(Spanned::from(Expr::Conditional {
                     condition: Box::new(Spanned::from(Expr::Ident("nice".to_owned()))),
-                    inner: Box::new(Spanned::from(30.0)),
-                    other: Box::new(Spanned::from(10.0))
-                }))
+                    inner: Box::new(Spanned::from(30)),
+                    other: Box::new(Spanned::from(10))
+                })),
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
             }
         )
     }
@@ -537,9 +1146,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Equals,
-                Box::new(Spanned::from(12.0))
+                Box::new(Spanned::from(12))
             )
         )
     }
@@ -551,9 +1160,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Lt,
-                Box::new(Spanned::from(12.0))
+                Box::new(Spanned::from(12))
             )
         )
     }
@@ -565,9 +1174,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Gt,
-                Box::new(Spanned::from(12.0))
+                Box::new(Spanned::from(12))
             )
         )
     }
@@ -579,9 +1188,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Lte,
-                Box::new(Spanned::from(12.0))
+                Box::new(Spanned::from(12))
             )
         )
     }
@@ -593,9 +1202,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(10.0)),
+                Box::new(Spanned::from(10)),
                 InfixOp::Gte,
-                Box::new(Spanned::from(12.0))
+                Box::new(Spanned::from(12))
             )
         )
     }
@@ -607,14 +1216,14 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(12.0)),
+                Box::new(Spanned::from(12)),
                 InfixOp::In,
                 Box::new(Spanned::from(vec![
-                    Spanned::from(10.0),
-                    Spanned::from(11.0),
-                    Spanned::from(12.0),
-                    Spanned::from(13.0),
-                    Spanned::from(14.0)
+                    Spanned::from(10),
+                    Spanned::from(11),
+                    Spanned::from(12),
+                    Spanned::from(13),
+                    Spanned::from(14)
                 ]))
             )
         )
@@ -628,7 +1237,10 @@ mod tests {
             parsed[0],
             Expr::Assign {
                 names: vec!["these".to_owned(), "are".to_owned(), "all".to_owned()],
-                value: Box::new(Spanned::from(12.0))
+                value: Box::new(Spanned::from(12)),
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
             }
         )
     }
@@ -640,9 +1252,9 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(0.0)),
+                Box::new(Spanned::from(0)),
                 InfixOp::IRange,
-                Box::new(Spanned::from(10.0))
+                Box::new(Spanned::from(10))
             )
         )
     }
@@ -654,24 +1266,549 @@ mod tests {
         assert_eq!(
             parsed[0],
             Expr::InfixOp(
-                Box::new(Spanned::from(0.0)),
+                Box::new(Spanned::from(0)),
                 InfixOp::Range,
-                Box::new(Spanned::from(10.0))
+                Box::new(Spanned::from(10))
             )
         )
     }
 
+    #[test]
+    fn parse_range_step() {
+        let parsed = parse("0..20 by 5");
+
+        assert_eq!(
+            parsed[0],
+            Expr::InfixOp(
+                Box::new(Spanned::from(Expr::InfixOp(
+                    Box::new(Spanned::from(0)),
+                    InfixOp::Range,
+                    Box::new(Spanned::from(20))
+                ))),
+                InfixOp::By,
+                Box::new(Spanned::from(5))
+            )
+        );
+        assert_eq!(String::from(&parsed[0]), "0..20 by 5");
+    }
+
+    #[test]
+    fn parse_chained_compare() {
+        let parsed = parse("1 <= x < 20 == true");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Compare {
+                first: Box::new(Spanned::from(1)),
+                rest: vec![
+                    (InfixOp::Lte, Spanned::from(Expr::Ident("x".to_owned()))),
+                    (InfixOp::Lt, Spanned::from(20)),
+                    (InfixOp::Equals, Spanned::from(true)),
+                ],
+            }
+        );
+        assert_eq!(parsed[0].1, 0..19);
+        assert_eq!(String::from(&parsed[0]), "1 <= x < 20 == true");
+
+        let parsed = parse("(1 <= x <= 20) && y + 1 <= 3");
+        assert_eq!(String::from(&parsed[0]), "(1 <= x <= 20) && ((y + 1) <= 3)");
+    }
+
     #[test]
     fn parse_typed_input() {
         let parsed = parse("input cool: Bool;");
 
-        assert_eq!(parsed[0], Expr::Input("cool".to_string(), ValueType::Bool))
+        assert_eq!(
+            parsed[0],
+            Expr::Input {
+                name: "cool".to_string(),
+                kind: ValueType::Bool,
+                default: None,
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
+            }
+        )
+    }
+
+    #[test]
+    fn parse_array_typed_input() {
+        let parsed = parse("input rolls: [[Int]];");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Input {
+                name: "rolls".to_string(),
+                kind: ValueType::ArrayOf(Box::new(ValueType::ArrayOf(Box::new(ValueType::Int)))),
+                default: None,
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
+            }
+        );
+        assert_eq!(String::from(&parsed[0]), "input rolls: [[Int]];");
+    }
+
+    #[test]
+    fn parse_unknown_input_type() {
+        // only the names in `TYPE_NAMES` are types, so lowercase spellings aren't quietly `Any`
+        for source in [
+            "input level: int;",
+            "input bonus: float;",
+            "input cool: bool;",
+        ] {
+            let len = source.len();
+            let lexed = lexer().parse(source).unwrap();
+
+            assert!(parser::parse()
+                .parse(Stream::from_iter(len..len + 1, lexed.into_iter()))
+                .is_err());
+        }
     }
 
     #[test]
     fn parse_untyped_input() {
         let parsed = parse("input nice;");
 
-        assert_eq!(parsed[0], Expr::Input("nice".to_string(), ValueType::Any))
+        assert_eq!(
+            parsed[0],
+            Expr::Input {
+                name: "nice".to_string(),
+                kind: ValueType::Any,
+                default: None,
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
+            }
+        )
+    }
+
+    #[test]
+    fn parse_block() {
+        let parsed = parse("scores { str = 10; input dex; }");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Block {
+                name: "scores".to_owned(),
+                items: vec![
+                    Spanned::from(Expr::Assign {
+                        names: vec!["str".to_owned()],
+                        value: Box::new(Spanned::from(10)),
+                        constraint: None,
+                        modifiers: Modifiers::default(),
+                        annotations: Vec::new()
+                    }),
+                    Spanned::from(Expr::Input {
+                        name: "dex".to_owned(),
+                        kind: ValueType::Any,
+                        default: None,
+                        constraint: None,
+                        modifiers: Modifiers::default(),
+                        annotations: Vec::new()
+                    })
+                ],
+                annotations: Vec::new()
+            }
+        )
+    }
+
+    #[test]
+    fn parse_access() {
+        let parsed = parse("scores.str[0]");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Index(
+                Box::new(Spanned::from(Expr::Access(
+                    Box::new(Spanned::from(Expr::Ident("scores".to_owned()))),
+                    Box::new(Spanned::from(Expr::Ident("str".to_owned())))
+                ))),
+                Box::new(Spanned::from(0))
+            )
+        )
+    }
+
+    #[test]
+    fn parse_call() {
+        let parsed = parse("clamp(nice, 0, 20)");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Call(
+                Box::new(Spanned::from(Expr::Ident("clamp".to_owned()))),
+                vec![
+                    Spanned::from(Expr::Ident("nice".to_owned())),
+                    Spanned::from(0),
+                    Spanned::from(20)
+                ]
+            )
+        )
+    }
+
+    #[test]
+    fn parse_constraint() {
+        let parsed = parse("input level where @ >= 1; hp = 10 where @ > 0;");
+
+        assert_eq!(
+            parsed,
+            vec![
+                Expr::Input {
+                    name: "level".to_owned(),
+                    kind: ValueType::Any,
+                    default: None,
+                    constraint: Some(Box::new(Spanned::from(Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("@".to_owned()))),
+                        InfixOp::Gte,
+                        Box::new(Spanned::from(1))
+                    )))),
+                    modifiers: Modifiers::default(),
+                    annotations: Vec::new()
+                },
+                Expr::Assign {
+                    names: vec!["hp".to_owned()],
+                    value: Box::new(Spanned::from(10)),
+                    constraint: Some(Box::new(Spanned::from(Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("@".to_owned()))),
+                        InfixOp::Gt,
+                        Box::new(Spanned::from(0))
+                    )))),
+                    modifiers: Modifiers::default(),
+                    annotations: Vec::new()
+                }
+            ]
+        )
+    }
+
+    #[test]
+    fn parse_input_default() {
+        let parsed = parse("input str = 10 where @ > 0;");
+
+        assert_eq!(
+            parsed,
+            vec![Expr::Input {
+                name: "str".to_owned(),
+                kind: ValueType::Any,
+                default: Some(Box::new(Spanned::from(10))),
+                constraint: Some(Box::new(Spanned::from(Expr::InfixOp(
+                    Box::new(Spanned::from(Expr::Ident("@".to_owned()))),
+                    InfixOp::Gt,
+                    Box::new(Spanned::from(0))
+                )))),
+                modifiers: Modifiers::default(),
+                annotations: Vec::new()
+            }]
+        )
+    }
+
+    #[test]
+    fn parse_annotations() {
+        let parsed = parse(r#"#[label("Strength"), order(1)] #[hidden(true)] pub input str;"#);
+
+        assert_eq!(
+            parsed[0],
+            Expr::Input {
+                name: "str".to_owned(),
+                kind: ValueType::Any,
+                default: None,
+                constraint: None,
+                modifiers: Modifiers {
+                    public: true,
+                    output: false
+                },
+                annotations: vec![
+                    ("label".to_owned(), Literal::String("Strength".to_owned())),
+                    ("order".to_owned(), Literal::Int(1)),
+                    ("hidden".to_owned(), Literal::Bool(true))
+                ]
+            }
+        )
+    }
+
+    #[test]
+    fn parse_unary() {
+        let parsed = parse("-x ** 2 - -(a + 1)");
+
+        assert_eq!(
+            parsed[0],
+            Expr::InfixOp(
+                Box::new(Spanned::from(Expr::Neg(Box::new(Spanned::from(
+                    Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("x".to_owned()))),
+                        InfixOp::Pow,
+                        Box::new(Spanned::from(2))
+                    )
+                ))))),
+                InfixOp::Sub,
+                Box::new(Spanned::from(Expr::Neg(Box::new(Spanned::from(
+                    Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("a".to_owned()))),
+                        InfixOp::Add,
+                        Box::new(Spanned::from(1))
+                    )
+                )))))
+            )
+        );
+
+        // printing it keeps the same tree
+        for source in [
+            "(-a) ** 2",
+            "(!a) ** 2",
+            "-a ** 2",
+            "(-2) ** 2",
+            "(-2.5) ** -a",
+        ] {
+            let parsed = parse(source);
+            assert_eq!(parse(&String::from(&parsed[0])), parsed, "{}", source);
+        }
+
+        let parsed = parse("(-2) ** -1");
+
+        assert_eq!(
+            parsed[0],
+            Expr::InfixOp(
+                Box::new(Spanned::from(-2)),
+                InfixOp::Pow,
+                Box::new(Spanned::from(-1))
+            )
+        )
+    }
+
+    #[test]
+    fn parse_not() {
+        let parsed = parse("not a == b and !c");
+
+        assert_eq!(
+            parsed[0],
+            Expr::InfixOp(
+                Box::new(Spanned::from(Expr::Not(Box::new(Spanned::from(
+                    Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("a".to_owned()))),
+                        InfixOp::Equals,
+                        Box::new(Spanned::from(Expr::Ident("b".to_owned())))
+                    )
+                ))))),
+                InfixOp::And,
+                Box::new(Spanned::from(Expr::Not(Box::new(Spanned::from(
+                    Expr::Ident("c".to_owned())
+                )))))
+            )
+        )
+    }
+
+    #[test]
+    fn parse_not_equals_null() {
+        let parsed = parse("cool != null");
+
+        assert_eq!(
+            parsed[0],
+            Expr::InfixOp(
+                Box::new(Spanned::from(Expr::Ident("cool".to_owned()))),
+                InfixOp::NotEquals,
+                Box::new(Spanned::from(Expr::Literal(Literal::Null)))
+            )
+        )
+    }
+
+    #[test]
+    fn parse_record() {
+        let parsed = parse("{ name: 'Rope', weight: 10, }.weight");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Access(
+                Box::new(Spanned::from(Expr::Literal(Literal::Record(vec![
+                    ("name".to_owned(), Spanned::from("Rope".to_owned())),
+                    ("weight".to_owned(), Spanned::from(10))
+                ])))),
+                Box::new(Spanned::from(Expr::Ident("weight".to_owned())))
+            )
+        )
+    }
+
+    #[test]
+    fn print_lambda_operands() {
+        // a lambda's body would swallow whatever comes after it without parens
+        for source in [
+            "(|x| x)(3)",
+            "(|x| x) + 1",
+            "1 + (|x| x)",
+            "(|x| x) == (|x| x)",
+            "1 < (|x| x) < 2",
+            "(|x| x)[0]",
+            "(|x| x).a",
+            "(a + b)(1)",
+        ] {
+            let parsed = parse(source);
+            assert_eq!(parse(&String::from(&parsed[0])), parsed, "{}", source);
+        }
+    }
+
+    #[test]
+    fn parse_lambda() {
+        let parsed = parse("(|a, b| a + b)(1, 2)");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Call(
+                Box::new(Spanned::from(Expr::Lambda {
+                    name: None,
+                    params: vec!["a".to_owned(), "b".to_owned()],
+                    body: Box::new(Spanned::from(Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("a".to_owned()))),
+                        InfixOp::Add,
+                        Box::new(Spanned::from(Expr::Ident("b".to_owned())))
+                    )))
+                })),
+                vec![Spanned::from(1), Spanned::from(2)]
+            )
+        );
+
+        let parsed = parse("fn double(x) = x * 2;");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Assign {
+                names: vec!["double".to_owned()],
+                value: Box::new(Spanned::from(Expr::Lambda {
+                    name: Some("double".to_owned()),
+                    params: vec!["x".to_owned()],
+                    body: Box::new(Spanned::from(Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("x".to_owned()))),
+                        InfixOp::Mul,
+                        Box::new(Spanned::from(2))
+                    )))
+                })),
+                constraint: None,
+                modifiers: Modifiers::default(),
+                annotations: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_comprehension() {
+        let parsed = parse("[x * 2 for x in items if x > 0]");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Comprehension {
+                item: Box::new(Spanned::from(Expr::InfixOp(
+                    Box::new(Spanned::from(Expr::Ident("x".to_owned()))),
+                    InfixOp::Mul,
+                    Box::new(Spanned::from(2))
+                ))),
+                var: "x".to_owned(),
+                source: Box::new(Spanned::from(Expr::Ident("items".to_owned()))),
+                condition: Some(Box::new(Spanned::from(Expr::InfixOp(
+                    Box::new(Spanned::from(Expr::Ident("x".to_owned()))),
+                    InfixOp::Gt,
+                    Box::new(Spanned::from(0))
+                ))))
+            }
+        )
+    }
+
+    #[test]
+    fn parse_format() {
+        let parsed = parse("\"{name} ({mod:+.1})\"");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Format(vec![
+                FormatPart::Value(
+                    Spanned::from(Expr::Ident("name".to_owned())),
+                    FormatSpec::default()
+                ),
+                FormatPart::Text(" (".to_owned()),
+                FormatPart::Value(
+                    Spanned::from(Expr::Ident("mod".to_owned())),
+                    FormatSpec {
+                        sign: true,
+                        precision: Some(1),
+                        ..FormatSpec::default()
+                    }
+                ),
+                FormatPart::Text(")".to_owned()),
+            ])
+        );
+
+        // spans inside the interpolation point into the whole source
+        match &parsed[0] {
+            Spanned(Expr::Format(parts), _) => match &parts[2] {
+                FormatPart::Value(value, _) => assert_eq!(value.1, 10..13),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let source = "\"{a + 1:>+04} {{ok}} {if b { 'x' } else { 'y' }}\"";
+        let parsed = parse(source);
+        let generated = String::from(&parsed[0]);
+
+        assert_eq!(parse(&generated), parsed);
+    }
+
+    #[test]
+    fn parse_match() {
+        let parsed = parse("match x { 1..=4 => 2, 5 | 6 => 3, _ => 4, }");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Match {
+                value: Box::new(Spanned::from(Expr::Ident("x".to_owned()))),
+                arms: vec![
+                    (
+                        vec![Spanned::from(Expr::InfixOp(
+                            Box::new(Spanned::from(1)),
+                            InfixOp::IRange,
+                            Box::new(Spanned::from(4))
+                        ))],
+                        Spanned::from(2)
+                    ),
+                    (vec![Spanned::from(5), Spanned::from(6)], Spanned::from(3)),
+                    (
+                        vec![Spanned::from(Expr::Ident("_".to_owned()))],
+                        Spanned::from(4)
+                    ),
+                ]
+            }
+        )
+    }
+
+    #[test]
+    fn parse_let() {
+        let parsed = parse("{ let a = 1; let b = a * 2; b + 1 }");
+
+        assert_eq!(
+            parsed[0],
+            Expr::Let {
+                name: "a".to_owned(),
+                value: Box::new(Spanned::from(1)),
+                body: Box::new(Spanned::from(Expr::Let {
+                    name: "b".to_owned(),
+                    value: Box::new(Spanned::from(Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("a".to_owned()))),
+                        InfixOp::Mul,
+                        Box::new(Spanned::from(2))
+                    ))),
+                    body: Box::new(Spanned::from(Expr::InfixOp(
+                        Box::new(Spanned::from(Expr::Ident("b".to_owned()))),
+                        InfixOp::Add,
+                        Box::new(Spanned::from(1))
+                    )))
+                }))
+            }
+        );
+
+        let generated = String::from(&parsed[0]);
+        assert_eq!(parse(&generated), parsed);
+
+        let parsed = parse("let a = 1 in let b = a * 2 in b + 1");
+        assert_eq!(parsed, parse("{ let a = 1; let b = a * 2; b + 1 }"));
+        assert_eq!(parsed[0].1, 0..35);
     }
 }
@@ -0,0 +1,93 @@
+use crate::{
+    ast::Span,
+    range::IntRange,
+    value::{SpannedValue, Value},
+};
+
+// the evaluated pattern of a `match` arm
+#[derive(Clone, Debug)]
+pub(crate) enum Pattern {
+    // `_`, matches anything
+    Wildcard(Span),
+    // `[1, _]`, matches arrays of the same length item by item
+    Array(Vec<Pattern>, Span),
+    // a range matches the numbers in it, anything else has to be equal
+    Value(Value, Span),
+}
+
+impl Pattern {
+    pub(crate) fn span(&self) -> &Span {
+        match self {
+            Pattern::Wildcard(span) | Pattern::Array(_, span) | Pattern::Value(_, span) => span,
+        }
+    }
+
+    pub(crate) fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (_, Value::Input(_, _, inner)) => self.matches(inner),
+            (Pattern::Wildcard(_), _) => true,
+            (Pattern::Array(patterns, _), Value::Array(items)) => {
+                patterns.len() == items.len()
+                    && patterns
+                        .iter()
+                        .zip(items)
+                        .all(|(pattern, item)| pattern.matches(&item.0))
+            }
+            (Pattern::Array(_, _), _) => false,
+            (Pattern::Value(Value::Range(range), _), Value::Int(e)) => range.contains(*e),
+            (Pattern::Value(Value::Range(range), _), Value::Num(e)) => range.contains_num(*e),
+            // `2` and `2.0` are the same number
+            (Pattern::Value(pattern, _), value) => pattern.same(value),
+        }
+    }
+
+    // whether everything `later` matches is already matched by this, so `later` can never be reached
+    pub(crate) fn covers(&self, later: &Pattern) -> bool {
+        match (self, later) {
+            (Pattern::Wildcard(_), _) => true,
+            (_, Pattern::Wildcard(_)) => false,
+            (Pattern::Array(earlier, _), Pattern::Array(later, _)) => {
+                earlier.len() == later.len()
+                    && earlier
+                        .iter()
+                        .zip(later)
+                        .all(|(earlier, later)| earlier.covers(later))
+            }
+            (Pattern::Value(_, _), Pattern::Array(items, _)) => {
+                let mut values = Vec::new();
+
+                for item in items {
+                    match item {
+                        Pattern::Value(value, span) => {
+                            values.push(SpannedValue(value.clone(), span.clone()))
+                        }
+                        _ => return false,
+                    }
+                }
+
+                self.matches(&Value::Array(values))
+            }
+            (_, Pattern::Value(Value::Range(range), _)) => {
+                range.is_empty() || self.covers_range(range)
+            }
+            (_, Pattern::Value(value, _)) => self.matches(value),
+        }
+    }
+
+    // ranges counting by 1 match every number between their ends, not just whole ones
+    // steps are ignored on `later`, since it can only match fewer numbers with them
+    fn covers_range(&self, later: &IntRange) -> bool {
+        match self {
+            Pattern::Value(Value::Range(range), _) if range.step == 1 => {
+                let ((low, low_closed), (high, high_closed)) = range.bounds();
+                let ((later_low, later_low_closed), (later_high, later_high_closed)) =
+                    later.bounds();
+
+                (low < later_low || (low == later_low && (low_closed || !later_low_closed)))
+                    && (later_high < high
+                        || (later_high == high && (high_closed || !later_high_closed)))
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::{self, Display};
+
+use serde::Serialize;
+
+// `start..end` or `start..=end`, going from `start` towards `end` by `step`
+// it counts down when `end` is before `start`, like `10..0`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct IntRange {
+    pub start: i64,
+    pub end: i64,
+    pub inclusive: bool,
+    pub step: u64, // at least 1, `by` only gives the size of the step and not its direction
+}
+
+impl IntRange {
+    pub fn new(start: i64, end: i64, inclusive: bool) -> Self {
+        Self {
+            start,
+            end,
+            inclusive,
+            step: 1,
+        }
+    }
+
+    pub fn descending(&self) -> bool {
+        self.end < self.start
+    }
+
+    // how many numbers are in the range
+    // a range over every `i64` has one more number than fits in a `u64`, so this is a `u128`
+    pub fn len(&self) -> u128 {
+        let distance = (self.end as i128 - self.start as i128).unsigned_abs();
+        let step = self.step as u128;
+
+        if self.inclusive {
+            distance / step + 1
+        } else {
+            distance.div_ceil(step)
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // every number in the range, in order
+    pub fn items(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len()).map(move |i| self.nth_item(i))
+    }
+
+    pub fn last(&self) -> Option<i64> {
+        self.len().checked_sub(1).map(|i| self.nth_item(i))
+    }
+
+    // the number at an index, counting from 0
+    pub fn nth(&self, i: u128) -> Option<i64> {
+        (i < self.len()).then(|| self.nth_item(i))
+    }
+
+    pub fn contains(&self, number: i64) -> bool {
+        let offset = if self.descending() {
+            self.start as i128 - number as i128
+        } else {
+            number as i128 - self.start as i128
+        };
+
+        let step = self.step as i128;
+
+        offset >= 0 && offset % step == 0 && self.len() > (offset / step) as u128
+    }
+
+    // ranges counting by 1 have every number between their ends in them, not just whole ones
+    // a stepped range like `0..20 by 5` only has the whole numbers it steps through
+    pub fn contains_num(&self, number: f64) -> bool {
+        if self.step != 1 {
+            return number == number.trunc() && self.contains(number as i64);
+        }
+
+        let ((low, low_closed), (high, high_closed)) = self.bounds();
+        let (low, high) = (low as f64, high as f64);
+
+        (low < number || (low_closed && low == number))
+            && (number < high || (high_closed && number == high))
+    }
+
+    // the lowest and highest ends, and whether each is part of the range, ignoring the step
+    pub fn bounds(&self) -> ((i64, bool), (i64, bool)) {
+        if self.descending() {
+            ((self.end, self.inclusive), (self.start, true))
+        } else {
+            ((self.start, true), (self.end, self.inclusive))
+        }
+    }
+
+    fn nth_item(&self, i: u128) -> i64 {
+        let distance = i as i128 * self.step as i128;
+
+        if self.descending() {
+            (self.start as i128 - distance) as i64
+        } else {
+            (self.start as i128 + distance) as i64
+        }
+    }
+}
+
+impl Display for IntRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = if self.inclusive { "..=" } else { ".." };
+
+        write!(f, "{}{}{}", self.start, op, self.end)?;
+
+        if self.step != 1 {
+            write!(f, " by {}", self.step)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IntRange;
+
+    #[test]
+    fn range_items() {
+        let items = |range: IntRange| range.items().collect::<Vec<i64>>();
+
+        assert_eq!(items(IntRange::new(0, 4, false)), vec![0, 1, 2, 3]);
+        assert_eq!(items(IntRange::new(0, 4, true)), vec![0, 1, 2, 3, 4]);
+        assert_eq!(items(IntRange::new(3, 0, false)), vec![3, 2, 1]);
+        assert_eq!(items(IntRange::new(2, 2, false)), Vec::<i64>::new());
+
+        let stepped = IntRange {
+            step: 5,
+            ..IntRange::new(0, 20, false)
+        };
+
+        assert_eq!(items(stepped.clone()), vec![0, 5, 10, 15]);
+        assert!(stepped.contains(15));
+        assert!(!stepped.contains(20));
+        assert!(!stepped.contains(7));
+        assert_eq!(stepped.to_string(), "0..20 by 5");
+
+        let everything = IntRange::new(i64::MIN, i64::MAX, true);
+
+        assert_eq!(everything.len(), u64::MAX as u128 + 1);
+        assert!(everything.contains(5));
+        assert_eq!(everything.last(), Some(i64::MAX));
+        assert_eq!(IntRange::new(10, 0, true).nth(3), Some(7));
+        assert_eq!(IntRange::new(10, 0, true).nth(11), None);
+    }
+}
@@ -0,0 +1,94 @@
+use std::ops::Range;
+
+use ariadne::{Cache, Report, ReportBuilder};
+
+use crate::ast::Span;
+
+// every file of a sheet, laid out one after another so a plain `Span` also says which file it's in
+#[derive(Clone, Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    pub path: String,
+    pub source: String,
+    pub offset: usize, // where the file starts among all the others
+}
+
+impl SourceMap {
+    pub fn new(path: &str, source: &str) -> Self {
+        let mut sources = Self::default();
+        sources.add(path, source);
+
+        sources
+    }
+
+    // returns the offset every span in the file has to be moved by
+    pub fn add(&mut self, path: &str, source: &str) -> usize {
+        // one past the end of the last file, so end of input spans stay in their own file
+        let offset = match self.files.last() {
+            Some(last) => last.offset + last.source.chars().count() + 1,
+            None => 0,
+        };
+
+        self.files.push(SourceFile {
+            path: path.to_owned(),
+            source: source.to_owned(),
+            offset,
+        });
+
+        offset
+    }
+
+    pub fn files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn file(&self, offset: usize) -> Option<&SourceFile> {
+        self.files.iter().rev().find(|file| file.offset <= offset)
+    }
+
+    // the file a span is in, and where it is inside that file
+    pub fn locate(&self, span: Span) -> (String, Range<usize>) {
+        match self.file(span.start) {
+            Some(file) => (
+                file.path.clone(),
+                span.start - file.offset..span.end.saturating_sub(file.offset),
+            ),
+            None => (String::new(), span),
+        }
+    }
+
+    pub(crate) fn report(&self, offset: usize) -> ReportBuilder<(String, Range<usize>)> {
+        let (path, span) = self.locate(offset..offset);
+
+        Report::build(ariadne::ReportKind::Error, path, span.start)
+    }
+
+    pub(crate) fn cache(&self) -> impl Cache<String> {
+        let files: Vec<(String, String)> = self
+            .files
+            .iter()
+            .map(|file| (file.path.clone(), file.source.clone()))
+            .collect();
+
+        ariadne::sources(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SourceMap;
+
+    #[test]
+    fn locate_span() {
+        let mut sources = SourceMap::new("main.plm", "a = 1;");
+        let offset = sources.add("rules/core.plm", "b = 2;");
+
+        assert_eq!(offset, 7);
+        assert_eq!(sources.locate(0..1), ("main.plm".to_owned(), 0..1));
+        assert_eq!(sources.locate(11..12), ("rules/core.plm".to_owned(), 4..5));
+    }
+}
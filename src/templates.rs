@@ -0,0 +1,385 @@
+use crate::{
+    ast::{Annotations, Expr, FormatPart, Literal, Modifiers, Spanned},
+    error::Error,
+};
+
+// replaces every member of a block with the declarations in its `common!` templates
+pub(crate) fn expand(items: &[Spanned]) -> Result<Vec<Spanned>, Vec<Error>> {
+    let mut errs: Vec<Error> = Vec::new();
+    let expanded = expand_items(items, &mut errs);
+
+    if errs.is_empty() {
+        Ok(expanded)
+    } else {
+        Err(errs)
+    }
+}
+
+fn expand_items(items: &[Spanned], errs: &mut Vec<Error>) -> Vec<Spanned> {
+    let template: Vec<Spanned> = items
+        .iter()
+        .filter_map(|item| match item {
+            Spanned(Expr::Template(template), _) => Some(template.clone()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    let mut out: Vec<Spanned> = Vec::new();
+
+    for item in items {
+        match item {
+            Spanned(
+                Expr::Block {
+                    name,
+                    items,
+                    annotations,
+                },
+                span,
+            ) => {
+                let block = Expr::Block {
+                    name: name.clone(),
+                    items: expand_items(items, errs),
+                    annotations: annotations.clone(),
+                };
+
+                out.push(Spanned(block, span.clone()));
+            }
+            Spanned(Expr::Template(statements), _) => reject_nested(statements, errs),
+            Spanned(Expr::Member { name, .. }, span) if template.is_empty() => {
+                errs.push(Error::MacroError {
+                    name: "common!".to_owned(),
+                    reason: format!(
+                        "`{}` has no value, and there's no template here to give it one",
+                        name
+                    ),
+                    span: span.clone(),
+                });
+            }
+            Spanned(Expr::Member { .. }, _) => {
+                for statement in &template {
+                    out.push(instantiate(statement, item, errs));
+                }
+            }
+            _ => out.push(item.clone()),
+        }
+    }
+
+    out
+}
+
+// templates are only expanded where they're written, so one inside another would be dropped
+fn reject_nested(statements: &[Spanned], errs: &mut Vec<Error>) {
+    for statement in statements {
+        match statement {
+            Spanned(Expr::Template(_), span) => errs.push(Error::MacroError {
+                name: "common!".to_owned(),
+                reason: "Templates can't be nested inside another template".to_owned(),
+                span: span.clone(),
+            }),
+            Spanned(Expr::Block { items, .. }, _) => reject_nested(items, errs),
+            _ => {}
+        }
+    }
+}
+
+// one statement of a template, declared for a single member
+fn instantiate(statement: &Spanned, member: &Spanned, errs: &mut Vec<Error>) -> Spanned {
+    let (member_name, member_modifiers, member_annotations) = match &member.0 {
+        Expr::Member {
+            name,
+            modifiers,
+            annotations,
+        } => (name, modifiers, annotations),
+        _ => unreachable!("Only members are instantiated"),
+    };
+
+    let rename = |name: &String| {
+        if name == "self" {
+            member_name.clone()
+        } else {
+            name.clone()
+        }
+    };
+
+    // the member's own annotations come last so they win over the template's
+    let annotations = |annotations: &Annotations| {
+        let mut annotations = annotations.clone();
+        annotations.extend(member_annotations.iter().cloned());
+
+        annotations
+    };
+
+    let expr = match &statement.0 {
+        Expr::Assign {
+            names,
+            value,
+            constraint,
+            modifiers,
+            annotations: found,
+        } => Expr::Assign {
+            names: names.iter().map(rename).collect(),
+            value: Box::new(substitute(value, member_name, errs)),
+            constraint: constraint
+                .as_ref()
+                .map(|e| Box::new(substitute(e, member_name, errs))),
+            modifiers: Modifiers {
+                public: modifiers.public || member_modifiers.public,
+                output: modifiers.output || member_modifiers.output,
+            },
+            annotations: annotations(found),
+        },
+        Expr::Input {
+            name,
+            kind,
+            default,
+            constraint,
+            modifiers,
+            annotations: found,
+        } => Expr::Input {
+            name: rename(name),
+            kind: kind.clone(),
+            default: default
+                .as_ref()
+                .map(|e| Box::new(substitute(e, member_name, errs))),
+            constraint: constraint
+                .as_ref()
+                .map(|e| Box::new(substitute(e, member_name, errs))),
+            modifiers: Modifiers {
+                public: modifiers.public || member_modifiers.public,
+                output: modifiers.output || member_modifiers.output,
+            },
+            annotations: annotations(found),
+        },
+        Expr::Block {
+            name,
+            items,
+            annotations: found,
+        } => Expr::Block {
+            name: rename(name),
+            items: items
+                .iter()
+                .map(|item| instantiate(item, member, errs))
+                .collect(),
+            annotations: annotations(found),
+        },
+        _ => substitute(statement, member_name, errs).0,
+    };
+
+    Spanned(expr, statement.1.clone())
+}
+
+// fills in `self`, `ident!()` and `concat!(..)` for a single member
+fn substitute(expr: &Spanned, member: &str, errs: &mut Vec<Error>) -> Spanned {
+    let substituted = match &expr.0 {
+        Expr::Ident(name) if name == "self" => Expr::Ident(member.to_owned()),
+        Expr::Macro(name, args) => match name.as_str() {
+            "ident" if args.is_empty() => Expr::Literal(Literal::String(member.to_owned())),
+            "ident" => {
+                errs.push(Error::ArityError {
+                    name: "ident!".to_owned(),
+                    expected: 0,
+                    got: args.len(),
+                    span: expr.1.clone(),
+                });
+
+                Expr::Error
+            }
+            // builds a reference out of pieces, like `concat!("scores.", ident!())`
+            "concat" => {
+                let mut path = String::new();
+
+                for arg in args {
+                    match substitute(arg, member, errs) {
+                        Spanned(Expr::Literal(Literal::String(e)), _) => path += &e,
+                        Spanned(Expr::Error, _) => {}
+                        Spanned(_, span) => errs.push(Error::MacroError {
+                            name: "concat!".to_owned(),
+                            reason: "This has to be a string or `ident!()`".to_owned(),
+                            span,
+                        }),
+                    }
+                }
+
+                Expr::Ident(path)
+            }
+            _ => {
+                errs.push(Error::MacroError {
+                    name: name.clone() + "!",
+                    reason: "This macro doesn't exist".to_owned(),
+                    span: expr.1.clone(),
+                });
+
+                Expr::Error
+            }
+        },
+        Expr::Literal(Literal::Array(items)) => Expr::Literal(Literal::Array(
+            items
+                .iter()
+                .map(|item| substitute(item, member, errs))
+                .collect(),
+        )),
+        Expr::Literal(Literal::Record(fields)) => Expr::Literal(Literal::Record(
+            fields
+                .iter()
+                .map(|(name, field)| (name.clone(), substitute(field, member, errs)))
+                .collect(),
+        )),
+        Expr::Not(rhs) => Expr::Not(Box::new(substitute(rhs, member, errs))),
+        Expr::Neg(rhs) => Expr::Neg(Box::new(substitute(rhs, member, errs))),
+        Expr::InfixOp(lhs, op, rhs) => Expr::InfixOp(
+            Box::new(substitute(lhs, member, errs)),
+            *op,
+            Box::new(substitute(rhs, member, errs)),
+        ),
+        Expr::Compare { first, rest } => Expr::Compare {
+            first: Box::new(substitute(first, member, errs)),
+            rest: rest
+                .iter()
+                .map(|(op, rhs)| (*op, substitute(rhs, member, errs)))
+                .collect(),
+        },
+        Expr::Index(lhs, idx) => Expr::Index(
+            Box::new(substitute(lhs, member, errs)),
+            Box::new(substitute(idx, member, errs)),
+        ),
+        Expr::Conditional {
+            condition,
+            inner,
+            other,
+        } => Expr::Conditional {
+            condition: Box::new(substitute(condition, member, errs)),
+            inner: Box::new(substitute(inner, member, errs)),
+            other: Box::new(substitute(other, member, errs)),
+        },
+        Expr::Access(lhs, rhs) => {
+            Expr::Access(Box::new(substitute(lhs, member, errs)), rhs.clone())
+        }
+        Expr::Call(callee, args) => Expr::Call(
+            Box::new(substitute(callee, member, errs)),
+            args.iter()
+                .map(|arg| substitute(arg, member, errs))
+                .collect(),
+        ),
+        Expr::Let { name, value, body } => Expr::Let {
+            name: name.clone(),
+            value: Box::new(substitute(value, member, errs)),
+            body: Box::new(substitute(body, member, errs)),
+        },
+        Expr::Match { value, arms } => Expr::Match {
+            value: Box::new(substitute(value, member, errs)),
+            arms: arms
+                .iter()
+                .map(|(patterns, body)| {
+                    let patterns = patterns
+                        .iter()
+                        .map(|pattern| substitute(pattern, member, errs))
+                        .collect();
+
+                    (patterns, substitute(body, member, errs))
+                })
+                .collect(),
+        },
+        Expr::Format(parts) => Expr::Format(
+            parts
+                .iter()
+                .map(|part| match part {
+                    FormatPart::Value(value, spec) => {
+                        FormatPart::Value(substitute(value, member, errs), spec.clone())
+                    }
+                    text => text.clone(),
+                })
+                .collect(),
+        ),
+        Expr::Comprehension {
+            item,
+            var,
+            source,
+            condition,
+        } => Expr::Comprehension {
+            item: Box::new(substitute(item, member, errs)),
+            var: var.clone(),
+            source: Box::new(substitute(source, member, errs)),
+            condition: condition
+                .as_ref()
+                .map(|e| Box::new(substitute(e, member, errs))),
+        },
+        Expr::Lambda { name, params, body } => Expr::Lambda {
+            name: name.clone(),
+            params: params.clone(),
+            body: Box::new(substitute(body, member, errs)),
+        },
+        other => other.clone(),
+    };
+
+    Spanned(substituted, expr.1.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{error::Error, interpreter::interpret, value::Value};
+
+    #[test]
+    fn expand_common() {
+        let store = interpret(
+            r#"scores {
+                common! { input self = 10 where @ >= 0; }
+
+                #[label("Strength")] str;
+                dex;
+            }
+
+            modifiers {
+                common! { out self = floor((concat!("scores.", ident!()) - 10) / 2); }
+
+                str;
+                dex;
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(store.values.get("modifiers.str").unwrap(), &Value::Int(0));
+        assert!(store.modifiers("modifiers.dex").output);
+        assert_eq!(
+            store
+                .annotations("scores.str")
+                .unwrap()
+                .get("label")
+                .unwrap(),
+            &Value::String("Strength".to_owned())
+        );
+    }
+
+    #[test]
+    fn expand_ident() {
+        let store = interpret("names { common! { self = ident!() * 2; } nice; }").unwrap();
+
+        assert_eq!(
+            store.values.get("names.nice").unwrap(),
+            &Value::String("nicenice".to_owned())
+        );
+    }
+
+    #[test]
+    fn member_without_template() {
+        match interpret("scores { str; }") {
+            Err(errs) => assert!(matches!(errs[0], Error::MacroError { .. })),
+            Ok(_) => panic!("expected a macro error"),
+        }
+    }
+
+    #[test]
+    fn nested_template() {
+        let source = "scores { common! { common! { bonus = 1; } self = 10; } str; }";
+
+        match interpret(source) {
+            Err(errs) => assert!(
+                matches!(&errs[..], [Error::MacroError { span, .. }] if span.start == source.find("common! { bonus").unwrap())
+            ),
+            Ok(_) => panic!("expected a macro error"),
+        }
+
+        let errs = interpret("a { common! { inner { common! { x = 1; } } } b; }").unwrap_err();
+        assert!(matches!(&errs[..], [Error::MacroError { .. }]));
+    }
+}
@@ -1,21 +1,33 @@
-use std::{collections::HashMap, fmt::Display, ops::Range};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    ops::Range,
+};
 
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 
-use crate::ast::Literal;
+use crate::{ast::Literal, dice::Dice, error::Error, function::Function, range::IntRange};
+
+// the most items an array, or characters a string, made while evaluating can have
+// so `sum(0..4000000000)` or `'a' * 4000000000` is an error rather than running out of memory
+pub const MAX_ITEMS: u128 = 1_000_000;
 
 #[derive(Clone, Debug, PartialEq, Serialize)]
 #[serde(untagged)]
 pub enum Value {
+    Int(i64),
     Num(f64),
     String(String),
     Bool(bool),
     Array(Vec<SpannedValue>),
+    Record(BTreeMap<String, SpannedValue>),
     Error,
     Assign(Vec<String>, Box<Value>),
-    Range(Range<isize>),
-    IRange(Range<isize>),
+    Range(IntRange),
     Input(String, ValueType, Box<Value>),
+    Dice(Dice),
+    Function(Function),
+    Null,
     None,
 }
 
@@ -25,25 +37,36 @@ pub struct ValueMap {
     pub values: HashMap<String, Value>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ValueType {
     Num,
     Int,
     String,
     Bool,
     Array,
+    // `[Int]`, an array with every item of the same type
+    ArrayOf(Box<ValueType>),
+    Record,
     Error,
     Assign,
     Range,
-    IRange,
     Input,
     Any,
     Null,
+    Dice,
+    Function,
 }
 
-#[derive(Clone, Debug, Serialize)]
+#[derive(Clone, Debug)]
 pub struct SpannedValue(pub Value, pub Range<usize>);
 
+// spans only matter for error reporting, so they're left out
+impl Serialize for SpannedValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
 impl From<ValueType> for Vec<ValueType> {
     fn from(f: ValueType) -> Self {
         vec![f]
@@ -77,48 +100,174 @@ impl From<Value> for SpannedValue {
 impl From<Literal> for Value {
     fn from(f: Literal) -> Self {
         match f {
+            Literal::Int(e) => Value::Int(e),
             Literal::Num(e) => Value::Num(e),
             Literal::String(e) => Value::String(e),
             Literal::Bool(e) => Value::Bool(e),
-            _ => Value::Error,
+            Literal::Dice(e) => Value::Dice(e),
+            Literal::Null => Value::Null,
+            Literal::Array(_) | Literal::Record(_) => Value::Error,
         }
     }
 }
 
+// how values show up inside interpolated strings
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(e) => write!(f, "{}", e),
+            Value::Num(e) => write!(f, "{}", e),
+            Value::String(e) => write!(f, "{}", e),
+            Value::Bool(e) => write!(f, "{}", e),
+            Value::Array(items) => {
+                let items: Vec<String> = items.iter().map(|item| item.0.to_string()).collect();
+
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Record(fields) if fields.is_empty() => write!(f, "{{}}"),
+            Value::Record(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value.0))
+                    .collect();
+
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Value::Error => write!(f, "[ERROR]"),
+            Value::Assign(_, value) => write!(f, "{}", value),
+            Value::Range(e) => write!(f, "{}", e),
+            Value::Input(_, _, value) => write!(f, "{}", value),
+            Value::Dice(e) => write!(f, "{}", e),
+            Value::Function(e) => write!(f, "{}", e),
+            Value::Null | Value::None => write!(f, "null"),
+        }
+    }
+}
+
+// the types that can be written in a sheet, like `input level: Int;`
+// the lexer, parser and `Display` all go by this, so a name means the same thing everywhere
+pub const TYPE_NAMES: [(&str, ValueType); 10] = [
+    ("Num", ValueType::Num),
+    ("Int", ValueType::Int),
+    ("String", ValueType::String),
+    ("Bool", ValueType::Bool),
+    ("Array", ValueType::Array),
+    ("Record", ValueType::Record),
+    ("Range", ValueType::Range),
+    ("Dice", ValueType::Dice),
+    ("Function", ValueType::Function),
+    ("Any", ValueType::Any),
+];
+
 impl Display for ValueType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((name, _)) = TYPE_NAMES.iter().find(|(_, kind)| kind == self) {
+            return write!(f, "{}", name);
+        }
+
         let out = match self {
-            ValueType::Num => "Num",
-            ValueType::Int => "Int",
-            ValueType::String => "String",
-            ValueType::Bool => "Bool",
-            ValueType::Array => "Array",
+            ValueType::ArrayOf(inner) => return write!(f, "[{}]", inner),
             ValueType::Error => "[ERROR]",
             ValueType::Assign => "Assign",
-            ValueType::Range => "Range",
-            ValueType::IRange => "IRange",
             ValueType::Input => "Input",
-            ValueType::Any => "Any",
             ValueType::Null => "Null",
+            _ => unreachable!("Everything else is in `TYPE_NAMES`"),
         };
 
         write!(f, "{}", out)
     }
 }
 
+// written the same way as in a sheet
+impl Serialize for ValueType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl ValueType {
+    // the type with a name from `TYPE_NAMES`
+    pub fn named(name: &str) -> Option<ValueType> {
+        TYPE_NAMES
+            .iter()
+            .find(|(kind_name, _)| *kind_name == name)
+            .map(|(_, kind)| kind.clone())
+    }
+
+    // whether a value given for something declared as this type is acceptable
+    pub fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (ValueType::Any, _) => true,
+            // ints can be used anywhere a number can
+            (ValueType::Num, Value::Int(_) | Value::Num(_)) => true,
+            (ValueType::ArrayOf(inner), Value::Array(items)) => {
+                items.iter().all(|item| inner.accepts(&item.0))
+            }
+            _ => value.get_type() == *self,
+        }
+    }
+}
+
 impl Value {
     pub fn get_type(&self) -> ValueType {
         match self {
+            Value::Int(_) => ValueType::Int,
             Value::Num(_) => ValueType::Num,
             Value::String(_) => ValueType::String,
             Value::Bool(_) => ValueType::Bool,
             Value::Array(_) => ValueType::Array,
+            Value::Record(_) => ValueType::Record,
             Value::Error => ValueType::Error,
             Value::Assign(_, _) => ValueType::Assign,
             Value::Range(_) => ValueType::Range,
-            Value::IRange(_) => ValueType::IRange,
             Value::Input(_, _, _) => ValueType::Input,
-            Value::None => ValueType::Null,
+            Value::Dice(_) => ValueType::Dice,
+            Value::Function(_) => ValueType::Function,
+            Value::Null | Value::None => ValueType::Null,
+        }
+    }
+
+    // the items of anything that can be looped over, with numbers in a range getting the span of the range
+    // `None` if it can't be looped over, and an error if it's a range with too many numbers to hold at once
+    pub fn items(&self, span: &Range<usize>) -> Option<Result<Vec<SpannedValue>, Error>> {
+        match self {
+            Value::Array(items) => Some(Ok(items.clone())),
+            Value::Range(range) if range.len() > MAX_ITEMS => Some(Err(Error::SizeError {
+                len: range.len(),
+                span: span.clone(),
+            })),
+            Value::Range(range) => Some(Ok(range
+                .items()
+                .map(|e| SpannedValue(Value::Int(e), span.clone()))
+                .collect())),
+            _ => None,
+        }
+    }
+
+    // equality the way `==` sees it, where `2` and `2.0` are the same number, even inside arrays and records
+    pub fn same(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(lhs), Value::Num(rhs)) => *lhs as f64 == *rhs,
+            (Value::Num(lhs), Value::Int(rhs)) => *lhs == *rhs as f64,
+            (Value::Array(lhs), Value::Array(rhs)) => {
+                lhs.len() == rhs.len() && lhs.iter().zip(rhs).all(|(lhs, rhs)| lhs.0.same(&rhs.0))
+            }
+            (Value::Record(lhs), Value::Record(rhs)) => {
+                lhs.len() == rhs.len()
+                    && lhs
+                        .iter()
+                        .all(|(name, lhs)| rhs.get(name).is_some_and(|rhs| lhs.0.same(&rhs.0)))
+            }
+            _ => self == other,
+        }
+    }
+
+    // any number as a float, for when exactness doesn't matter
+    pub fn as_num(&self) -> Option<f64> {
+        match self {
+            Value::Int(e) => Some(*e as f64),
+            Value::Num(e) => Some(*e),
+            _ => None,
         }
     }
 }